@@ -0,0 +1,85 @@
+//Compares the size and speed of the compression modes on NIF files.
+//
+//    cargo run --release --example codec_bench [FILE...]
+//
+//Without arguments the test files bundled with the crate are used.
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use nif::nif::{
+    Nif, WriteOptions, FEATURE_FLAGS_CODEC_QOI, FEATURE_FLAGS_COMPRESSION,
+    FEATURE_FLAGS_FRAME_INDEX, FEATURE_FLAGS_RLE,
+};
+
+const RUNS: u32 = 5;
+
+//the fastest of RUNS runs
+fn time<T>(mut f: impl FnMut() -> T) -> (T, Duration) {
+    let mut best = Duration::MAX;
+    let mut result = None;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        result = Some(f());
+        best = best.min(start.elapsed());
+    }
+    (result.unwrap(), best)
+}
+
+fn modes() -> Vec<(&'static str, u32)> {
+    let records = FEATURE_FLAGS_COMPRESSION | FEATURE_FLAGS_FRAME_INDEX;
+    #[allow(unused_mut)]
+    let mut modes = vec![
+        ("gzip stream", FEATURE_FLAGS_COMPRESSION),
+        ("gzip records", records),
+        ("qoi", records | FEATURE_FLAGS_CODEC_QOI),
+        ("rle", FEATURE_FLAGS_FRAME_INDEX | FEATURE_FLAGS_RLE),
+    ];
+    #[cfg(feature = "lz4")]
+    modes.push(("lz4", records | nif::nif::FEATURE_FLAGS_CODEC_LZ4));
+    #[cfg(feature = "zstd")]
+    modes.push(("zstd", records | nif::nif::FEATURE_FLAGS_CODEC_ZSTD));
+    modes
+}
+
+fn bench(path: &Path) {
+    let mut nif = Nif::new_default();
+    if let Err(e) = nif.read_from_file(path) {
+        println!("{}: {}", path.display(), e);
+        return;
+    }
+    let raw = nif.to_bytes(0).unwrap().len();
+    println!("{} ({} bytes uncompressed)", path.display(), raw);
+    for (name, features) in modes() {
+        let (bytes, encode) = time(|| nif.to_bytes(WriteOptions::new(features)).unwrap());
+        let (_, decode) = time(|| Nif::from_bytes(&bytes).unwrap());
+        let mb_per_s = |d: Duration| raw as f64 / d.as_secs_f64() / 1e6;
+        println!(
+            "  {:<13} {:>9} bytes  {:>6.2}x  encode {:>8.1} MB/s  decode {:>8.1} MB/s",
+            name,
+            bytes.len(),
+            raw as f64 / bytes.len() as f64,
+            mb_per_s(encode),
+            mb_per_s(decode),
+        );
+    }
+}
+
+fn main() {
+    let mut paths: Vec<PathBuf> = std::env::args().skip(1).map(PathBuf::from).collect();
+    if paths.is_empty() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        for name in [
+            "test.nif",
+            "test_comp.nif",
+            "test_rng.nif",
+            "test_comp_rng.nif",
+        ] {
+            paths.push(dir.join(name));
+        }
+    }
+    for path in paths {
+        bench(&path);
+    }
+}
@@ -1,18 +1,130 @@
 pub mod nif {
     use std::{
         fs::File,
-        io::{BufRead, BufReader, BufWriter, Read, Result, Write},
+        io::{BufReader, BufWriter, Read, Write},
         path::Path,
     };
 
-    use flate2::{bufread::GzDecoder, write::GzEncoder};
+    mod chunks;
+    mod codec;
+    mod color;
+    mod counting;
+    mod error;
+    mod exif;
+    mod filter;
+    mod interlace;
+    #[cfg(feature = "mmap")]
+    mod mapped;
+    mod metadata;
+    mod mipmap;
+    mod qoi;
+    mod reader;
+    mod records;
+    mod rects;
+    mod rle;
+    mod tiles;
+    mod time;
+    mod writer;
+    #[cfg(feature = "lz4")]
+    pub use codec::Lz4;
+    #[cfg(feature = "zstd")]
+    pub use codec::Zstd;
+    pub use codec::{
+        find_codec, Codec, Deflate, Gzip, Qoi, CODEC_DEFLATE, CODEC_GZIP, CODEC_LZ4, CODEC_QOI,
+        CODEC_ZSTD,
+    };
+    pub use color::{ColorPrimaries, ColorSpace, TransferFunction};
+    use counting::Counting;
+    pub use error::{Error, Result};
+    pub use exif::Orientation;
+    #[cfg(feature = "mmap")]
+    pub use mapped::{MappedNif, MappedNifMut};
+    pub use metadata::{
+        Metadata, METADATA_AUTHOR, METADATA_CREATED, METADATA_SOFTWARE, METADATA_TITLE,
+    };
+    pub use mipmap::MipmapFilter;
+    pub use reader::{decode_partial, NifReader, Preview};
+    pub use tiles::{Tile, TILE_SIZE};
+    pub use time::{Rational, Timestamp};
+    pub use writer::{NifWriter, WriteOptions};
+
     //Magic number for NIF file
     pub const MAGIC_NUMBER: u32 = 0x4E494600;
-    pub const CURRENT_VERSION: u32 = 0x00010000;
+    //the header is followed by the payload
+    pub const VERSION_0_1: u32 = 0x00010000;
+    //the header and payload are stored in typed chunks after the feature
+    //flags, see README.md
+    pub const VERSION_0_2: u32 = 0x00020000;
+    //the frame rate in the header is a fraction instead of an f32
+    pub const VERSION_0_3: u32 = 0x00030000;
+    pub const CURRENT_VERSION: u32 = VERSION_0_3;
+    //the size of the header before version 0.3
     pub const HEADER_SIZE: usize = 0x14;
+    pub const HEADER_SIZE_0_3: usize = 0x18;
     pub const FEATURE_FLAGS_COMPRESSION: u32 = 0x1;
+    //every frame is stored as its own record, compressed on its own if
+    //FEATURE_FLAGS_COMPRESSION is set, and an index of the records follows
+    //the last frame so single frames can be read without decoding the rest
+    pub const FEATURE_FLAGS_FRAME_INDEX: u32 = 0x2;
+    //every row is run through a PNG style prediction filter before it is
+    //compressed. Requires FEATURE_FLAGS_COMPRESSION
+    pub const FEATURE_FLAGS_FILTER: u32 = 0x4;
+    //frames between keyframes are stored as the XOR of the frame and the
+    //frame before it. Requires FEATURE_FLAGS_FRAME_INDEX
+    pub const FEATURE_FLAGS_DELTA: u32 = 0x8;
+    //frames may be stored as the rectangles that changed since the frame
+    //before them. Requires FEATURE_FLAGS_FRAME_INDEX
+    pub const FEATURE_FLAGS_DIRTY_RECTS: u32 = 0x10;
+    //every row of a frame is stored run-length encoded over its pixel
+    //values instead of compressed. Requires FEATURE_FLAGS_FRAME_INDEX and
+    //cannot be combined with FEATURE_FLAGS_COMPRESSION
+    pub const FEATURE_FLAGS_RLE: u32 = 0x20;
+    //frames are stored as tiles of TILE_SIZE squared pixels that can be
+    //decoded on their own. Requires FEATURE_FLAGS_FRAME_INDEX and cannot be
+    //combined with FEATURE_FLAGS_DELTA or FEATURE_FLAGS_DIRTY_RECTS
+    pub const FEATURE_FLAGS_TILES: u32 = 0x40;
+    //the pixels of every frame are stored in the seven passes of Adam7
+    //interlacing, so the start of a frame is enough for a preview of all of
+    //it, see decode_partial
+    pub const FEATURE_FLAGS_INTERLACE: u32 = 0x80;
+    //ancillary features only add data that can be skipped, readers ignore
+    //the ones they do not know and read the file without them
+    pub const FEATURE_FLAGS_ANCILLARY_MASK: u32 = 0xFFFF0000;
+    //an image of one frame is followed by its mipmap levels. Ancillary,
+    //requires FEATURE_FLAGS_FRAME_INDEX
+    pub const FEATURE_FLAGS_MIPMAPS: u32 = 0x10000;
+    //how often NifWriter writes a full frame with FEATURE_FLAGS_DELTA or
+    //FEATURE_FLAGS_DIRTY_RECTS
+    pub const DEFAULT_KEYFRAME_INTERVAL: u32 = 30;
+    //the id of the codec used with FEATURE_FLAGS_COMPRESSION, 0 is gzip. Codecs
+    //other than gzip require FEATURE_FLAGS_FRAME_INDEX
+    pub const FEATURE_FLAGS_CODEC_MASK: u32 = 0xF00;
+    pub const FEATURE_FLAGS_CODEC_SHIFT: u32 = 8;
+    pub const FEATURE_FLAGS_CODEC_DEFLATE: u32 = CODEC_DEFLATE << FEATURE_FLAGS_CODEC_SHIFT;
+    pub const FEATURE_FLAGS_CODEC_ZSTD: u32 = CODEC_ZSTD << FEATURE_FLAGS_CODEC_SHIFT;
+    pub const FEATURE_FLAGS_CODEC_LZ4: u32 = CODEC_LZ4 << FEATURE_FLAGS_CODEC_SHIFT;
+    pub const FEATURE_FLAGS_CODEC_QOI: u32 = CODEC_QOI << FEATURE_FLAGS_CODEC_SHIFT;
+    //frame count of streams whose length was not known when the header was
+    //written, readers read frames until the end of the stream
+    pub const FRAME_COUNT_UNKNOWN: u32 = 0xFFFFFFFF;
+    //every feature flag this implementation can read and write
+    pub const SUPPORTED_FEATURE_FLAGS: u32 = FEATURE_FLAGS_COMPRESSION
+        | FEATURE_FLAGS_FRAME_INDEX
+        | FEATURE_FLAGS_FILTER
+        | FEATURE_FLAGS_DELTA
+        | FEATURE_FLAGS_DIRTY_RECTS
+        | FEATURE_FLAGS_RLE
+        | FEATURE_FLAGS_TILES
+        | FEATURE_FLAGS_INTERLACE
+        | FEATURE_FLAGS_MIPMAPS
+        | FEATURE_FLAGS_CODEC_MASK;
+    //magic number, version and feature flags come before the header
+    const PAYLOAD_OFFSET: u64 = 12 + HEADER_SIZE as u64;
 
-    //describes how the pixel data is stored
+    //describes how the pixel data is stored. Channels are always laid out
+    //R, G, B, A from the most significant bits down, and the packed value is
+    //stored big-endian, so the bytes in memory and on disk read R, G, B, A.
+    //The RGB formats keep the alpha slot as padding.
     #[derive(Clone, Copy, PartialEq, Eq, Debug)]
     pub enum Pixel {
         RGBA8888(Pixel32U),
@@ -41,7 +153,7 @@ pub mod nif {
 
         pub fn from_rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
             Pixel32U {
-                rgba: (a as u32) << 24 | (b as u32) << 16 | (g as u32) << 8 | (r as u32),
+                rgba: (r as u32) << 24 | (g as u32) << 16 | (b as u32) << 8 | (a as u32),
             }
         }
         pub fn r(&self) -> u8 {
@@ -80,30 +192,43 @@ pub mod nif {
         fn from_u16(rgb: u16) -> Pixel16U {
             Pixel16U { rgb }
         }
-        fn new(r: u8, g: u8, b: u8) -> Pixel16U {
+        //channels are 4 bits wide, higher bits of the arguments are ignored
+        pub fn new(r: u8, g: u8, b: u8) -> Pixel16U {
+            Self::from_rgba(r, g, b, 0)
+        }
+        pub fn from_rgba(r: u8, g: u8, b: u8, a: u8) -> Pixel16U {
             Pixel16U {
-                rgb: (b as u16) << 12 | (g as u16) << 8 | (r as u16),
+                rgb: (r as u16 & 0xF) << 12
+                    | (g as u16 & 0xF) << 8
+                    | (b as u16 & 0xF) << 4
+                    | (a as u16 & 0xF),
             }
         }
-        fn r(&self) -> u8 {
-            (self.rgb >> 12) as u8
+        pub fn r(&self) -> u8 {
+            (self.rgb >> 12) as u8 & 0xF
+        }
+        pub fn g(&self) -> u8 {
+            (self.rgb >> 8) as u8 & 0xF
+        }
+        pub fn b(&self) -> u8 {
+            (self.rgb >> 4) as u8 & 0xF
         }
-        fn g(&self) -> u8 {
-            (self.rgb >> 8) as u8
+        pub fn a(&self) -> u8 {
+            self.rgb as u8 & 0xF
         }
-        fn b(&self) -> u8 {
-            self.rgb as u8
+        pub fn set_r(&mut self, r: u8) {
+            self.rgb = (self.rgb & 0x0FFF) | ((r as u16 & 0xF) << 12);
         }
-        fn set_r(&mut self, r: u8) {
-            self.rgb = (self.rgb & 0x0FFF) | ((r as u16) << 12);
+        pub fn set_g(&mut self, g: u8) {
+            self.rgb = (self.rgb & 0xF0FF) | ((g as u16 & 0xF) << 8);
         }
-        fn set_g(&mut self, g: u8) {
-            self.rgb = (self.rgb & 0xF0FF) | ((g as u16) << 8);
+        pub fn set_b(&mut self, b: u8) {
+            self.rgb = (self.rgb & 0xFF0F) | ((b as u16 & 0xF) << 4);
         }
-        fn set_b(&mut self, b: u8) {
-            self.rgb = (self.rgb & 0xFFF0) | ((b as u16) << 0);
+        pub fn set_a(&mut self, a: u8) {
+            self.rgb = (self.rgb & 0xFFF0) | (a as u16 & 0xF);
         }
-        fn get(&self) -> u16 {
+        pub fn get(&self) -> u16 {
             self.rgb
         }
     }
@@ -128,15 +253,50 @@ pub mod nif {
         pub height: u32,
         pub pixel_format: Pixel,
         pub frame_count: u32,
-        pub frame_rate: f32,
+        //frames per second, 30000/1001 for NTSC video. Files before version
+        //0.3 store it as an f32, see Rational::from_f32
+        pub rate: Rational,
+    }
+    impl Header {
+        //The frame rate as an f32, like it was stored before version 0.3.
+        pub fn frame_rate(&self) -> f32 {
+            self.rate.to_f64() as f32
+        }
+        //size in bytes of a single frame, None if it does not fit in a usize
+        pub fn frame_size(&self) -> Option<usize> {
+            (self.width as usize)
+                .checked_mul(self.height as usize)?
+                .checked_mul(self.pixel_format.get_size())
+        }
+        //The header of mipmap level `n` of an image, level 0 is the image.
+        pub fn level(&self, n: u32) -> Header {
+            let scale = |size: u32| size.checked_shr(n).unwrap_or(0).max(1);
+            Header {
+                width: scale(self.width),
+                height: scale(self.height),
+                frame_count: 1,
+                ..*self
+            }
+        }
+        //The number of mipmap levels of an image, counting the image itself.
+        pub fn level_count(&self) -> u32 {
+            if self.width == 0 || self.height == 0 {
+                return 1;
+            }
+            32 - self.width.max(self.height).leading_zeros()
+        }
     }
     #[derive(PartialEq, Eq, Ord, PartialOrd, Debug)]
     pub struct Frame {
         pub data: Vec<u8>,
+        timestamp: Option<Timestamp>,
     }
     impl Frame {
         pub fn from(data: Vec<u8>) -> Self {
-            Self { data }
+            Self {
+                data,
+                timestamp: None,
+            }
         }
         pub fn new(header: Header) -> Self {
             //create a new frame with black pixels
@@ -145,20 +305,103 @@ pub mod nif {
                     0;
                     header.width as usize * header.height as usize * header.pixel_format.get_size()
                 ];
+            Self::from(data)
+        }
+        //When the frame is shown. Frames read from files without timestamps
+        //have none, and either all frames written to a file have one, in the
+        //same timebase, or none.
+        pub fn timestamp(&self) -> Option<Timestamp> {
+            self.timestamp
+        }
+        pub fn set_timestamp(&mut self, timestamp: Option<Timestamp>) {
+            self.timestamp = timestamp;
+        }
+        pub fn get_pixel(&self, x: u32, y: u32, header: Header) -> Pixel {
+            self.as_frame_ref().get_pixel(x, y, header)
+        }
+        pub fn set_pixel(&mut self, x: u32, y: u32, pixel: Pixel, header: Header) {
+            self.as_frame_mut().set_pixel(x, y, pixel, header)
+        }
+        pub fn pixels<'b>(&'b self, header: &'b Header) -> PixelIterator<'b> {
+            PixelIterator::new(self, header)
+        }
+        pub fn as_frame_ref(&self) -> FrameRef<'_> {
+            FrameRef { data: &self.data }
+        }
+        pub fn as_frame_mut(&mut self) -> FrameMut<'_> {
+            FrameMut {
+                data: &mut self.data,
+            }
+        }
+    }
+
+    //A frame whose pixels are borrowed, e.g. from a memory mapped file.
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub struct FrameRef<'a> {
+        data: &'a [u8],
+    }
+    impl<'a> FrameRef<'a> {
+        pub fn from(data: &'a [u8]) -> Self {
             Self { data }
         }
+        pub fn data(&self) -> &'a [u8] {
+            self.data
+        }
         pub fn get_pixel(&self, x: u32, y: u32, header: Header) -> Pixel {
-            let pixel_size = match header.pixel_format {
-                Pixel::RGBA8888(_) => 4,
-                Pixel::RGB888(_) => 4,
-                Pixel::RGBA4444(_) => 2,
-                Pixel::RGB444(_) => 2,
-            };
-            let pixel_offset = (y * header.width + x) * pixel_size;
-            let range = pixel_offset as usize..(pixel_offset + pixel_size) as usize;
-            let pixel_data = &self.data[range];
+            let range = pixel_range(x, y, &header);
+            Pixel::from_be_bytes(&header.pixel_format, &self.data[range])
+        }
+        pub fn pixels<'b>(&self, header: &'b Header) -> PixelIterator<'b>
+        where
+            'a: 'b,
+        {
+            PixelIterator {
+                data: self.data,
+                current_pixel: 0,
+                header,
+            }
+        }
+        //copies the pixels into an owned frame
+        pub fn to_frame(&self) -> Frame {
+            Frame::from(self.data.to_vec())
+        }
+    }
+
+    //A frame whose pixels are mutably borrowed, e.g. from a writable memory map.
+    #[derive(PartialEq, Eq, Debug)]
+    pub struct FrameMut<'a> {
+        data: &'a mut [u8],
+    }
+    impl<'a> FrameMut<'a> {
+        pub fn from(data: &'a mut [u8]) -> Self {
+            Self { data }
+        }
+        pub fn data(&self) -> &[u8] {
+            self.data
+        }
+        pub fn data_mut(&mut self) -> &mut [u8] {
+            self.data
+        }
+        pub fn get_pixel(&self, x: u32, y: u32, header: Header) -> Pixel {
+            FrameRef::from(self.data).get_pixel(x, y, header)
+        }
+        pub fn set_pixel(&mut self, x: u32, y: u32, pixel: Pixel, header: Header) {
+            let range = pixel_range(x, y, &header);
+            pixel.to_be_bytes(&mut self.data[range]);
+        }
+    }
 
-            match &header.pixel_format {
+    //byte range of the pixel at x, y
+    fn pixel_range(x: u32, y: u32, header: &Header) -> std::ops::Range<usize> {
+        let pixel_size = header.pixel_format.get_size();
+        let pixel_offset = (y as usize * header.width as usize + x as usize) * pixel_size;
+        pixel_offset..pixel_offset + pixel_size
+    }
+
+    impl Pixel {
+        //decodes the stored bytes of a pixel in the given format
+        fn from_be_bytes(format: &Pixel, pixel_data: &[u8]) -> Pixel {
+            match format {
                 Pixel::RGBA8888(_) => Pixel::RGBA8888(Pixel32U::from_u32(u32::from_be_bytes(
                     pixel_data.try_into().unwrap(),
                 ))),
@@ -173,50 +416,29 @@ pub mod nif {
                 ))),
             }
         }
-        pub fn set_pixel(&mut self, x: u32, y: u32, pixel: Pixel, header: Header) {
-            let pixel_size = match header.pixel_format {
-                Pixel::RGBA8888(_) => 4,
-                Pixel::RGB888(_) => 4,
-                Pixel::RGBA4444(_) => 2,
-                Pixel::RGB444(_) => 2,
-            };
-            let pixel_offset = (y * header.width + x) * pixel_size;
-            let range = pixel_offset as usize..(pixel_offset + pixel_size) as usize;
-            let pixel_data = &mut self.data[range];
-
-            match pixel {
+        fn to_be_bytes(self, pixel_data: &mut [u8]) {
+            match self {
                 Pixel::RGBA8888(val) => {
-                    pixel_data.copy_from_slice(&val.get().to_le_bytes());
+                    pixel_data.copy_from_slice(&val.get().to_be_bytes());
                 }
-                Pixel::RGB888(val) => pixel_data.copy_from_slice(&val.get().to_le_bytes()),
+                Pixel::RGB888(val) => pixel_data.copy_from_slice(&val.get().to_be_bytes()),
                 Pixel::RGBA4444(val) => {
-                    pixel_data.copy_from_slice(&val.get().to_le_bytes());
+                    pixel_data.copy_from_slice(&val.get().to_be_bytes());
                 }
-                Pixel::RGB444(val) => pixel_data.copy_from_slice(&val.get().to_le_bytes()),
+                Pixel::RGB444(val) => pixel_data.copy_from_slice(&val.get().to_be_bytes()),
             }
         }
     }
-    // //impl Into PixelIterator for Frame
-    // impl<'b> IntoIterator for &'b Frame {
-    //     type Item = Pixel;
-    //     type IntoIter = PixelIterator<'b>;
-    //     fn into_iter(self) -> Self::IntoIter {
-    //         PixelIterator {
-    //             frame: self,
-    //             current_pixel: 0,
 
-    //         }
-    //     }
-    // }
     pub struct PixelIterator<'b> {
-        frame: &'b Frame,
+        data: &'b [u8],
         header: &'b Header,
-        current_pixel: u32,
+        current_pixel: usize,
     }
     impl<'b> PixelIterator<'b> {
-        fn new(frame: &'b Frame, header: &'b Header) -> Self {
+        pub fn new(frame: &'b Frame, header: &'b Header) -> Self {
             Self {
-                frame,
+                data: &frame.data,
                 current_pixel: 0,
                 header,
             }
@@ -226,31 +448,12 @@ pub mod nif {
     impl<'b> Iterator for PixelIterator<'b> {
         type Item = Pixel;
         fn next(&mut self) -> Option<Self::Item> {
-            let pixel_size = match self.header.pixel_format {
-                Pixel::RGBA8888(_) => 4,
-                Pixel::RGB888(_) => 4,
-                Pixel::RGBA4444(_) => 2,
-                Pixel::RGB444(_) => 2,
-            };
-            let pixel_offset = self.current_pixel * pixel_size;
-            let range = pixel_offset as usize..(pixel_offset + pixel_size) as usize;
-            let pixel_data = &self.frame.data[range];
-            self.current_pixel += 1;
-            if self.current_pixel < self.header.width * self.header.height {
-                match self.header.pixel_format {
-                    Pixel::RGBA8888(_) => Some(Pixel::RGBA8888(
-                        u32::from_be_bytes(pixel_data.try_into().unwrap()).into(),
-                    )),
-                    Pixel::RGB888(_) => Some(Pixel::RGB888(
-                        u32::from_be_bytes(pixel_data.try_into().unwrap()).into(),
-                    )),
-                    Pixel::RGBA4444(_) => Some(Pixel::RGBA4444(
-                        u16::from_be_bytes(pixel_data.try_into().unwrap()).into(),
-                    )),
-                    Pixel::RGB444(_) => Some(Pixel::RGB444(
-                        u16::from_be_bytes(pixel_data.try_into().unwrap()).into(),
-                    )),
-                }
+            let pixel_size = self.header.pixel_format.get_size();
+            if self.current_pixel < self.header.width as usize * self.header.height as usize {
+                let pixel_offset = self.current_pixel * pixel_size;
+                let pixel_data = &self.data[pixel_offset..pixel_offset + pixel_size];
+                self.current_pixel += 1;
+                Some(Pixel::from_be_bytes(&self.header.pixel_format, pixel_data))
             } else {
                 None
             }
@@ -262,6 +465,14 @@ pub mod nif {
         pub features: u32,
         pub header: Header,
         frames: Vec<Frame>,
+        //mipmap levels 1 and up of the first frame, empty unless read from a
+        //file with FEATURE_FLAGS_MIPMAPS or generated
+        levels: Vec<Frame>,
+        metadata: Metadata,
+        color_space: ColorSpace,
+        icc_profile: Option<Vec<u8>>,
+        exif: Option<Vec<u8>>,
+        xmp: Option<Vec<u8>>,
     }
 
     impl Nif {
@@ -274,9 +485,15 @@ pub mod nif {
                     height: 0,
                     pixel_format: Pixel::RGBA8888(Pixel32U::default()),
                     frame_count: 0,
-                    frame_rate: 0.0,
+                    rate: Rational::new(0, 1),
                 },
                 frames: Vec::new(),
+                levels: Vec::new(),
+                metadata: Metadata::new(),
+                color_space: ColorSpace::SRGB,
+                icc_profile: None,
+                exif: None,
+                xmp: None,
             }
         }
         pub fn new(header: Header) -> Self {
@@ -285,6 +502,12 @@ pub mod nif {
                 features: 0,
                 header,
                 frames: Vec::new(),
+                levels: Vec::new(),
+                metadata: Metadata::new(),
+                color_space: ColorSpace::SRGB,
+                icc_profile: None,
+                exif: None,
+                xmp: None,
             }
         }
         //Returns an iterator over the pixels of the frame at index
@@ -302,138 +525,573 @@ pub mod nif {
             &mut self.frames
         }
         pub fn read_from_file(&mut self, path: &Path) -> Result<()> {
-            let mut buf = std::io::BufReader::new(std::fs::File::open(path).unwrap());
-            let mut magic_number = [0; 4];
-            buf.read_exact(&mut magic_number).unwrap();
-            let magic_number = u32::from_be_bytes(magic_number);
-            if magic_number != MAGIC_NUMBER {
-                panic!("Invalid magic number. This is not a NIF file.");
-            }
-
-            let mut version_buf = [0; 4];
-            buf.read_exact(&mut version_buf).unwrap();
-            let version = u32::from_be_bytes(version_buf);
-            if version > CURRENT_VERSION {
-                panic!("Invalid version. This NIF file is not supported.");
-            }
-            let mut feature_flags = [0; 4];
-            buf.read_exact(&mut feature_flags).unwrap();
-            let feature_flags = u32::from_be_bytes(feature_flags);
-
-            self.version = version;
-            self.features = feature_flags;
-            let mut header_buf = [0; HEADER_SIZE];
-            buf.read_exact(&mut header_buf).unwrap();
-            let header: Header = Header {
-                width: u32::from_be_bytes(header_buf[0..4].try_into().unwrap()),
-                height: u32::from_be_bytes(header_buf[4..8].try_into().unwrap()),
-                pixel_format: match u32::from_be_bytes(header_buf[8..12].try_into().unwrap()) {
-                    0 => Pixel::RGBA8888(0.into()),
-                    1 => Pixel::RGB888(0.into()),
-                    2 => Pixel::RGBA4444(0.into()),
-                    3 => Pixel::RGB444(0.into()),
-                    _ => panic!("Invalid pixel format."),
-                },
-                frame_count: u32::from_be_bytes(header_buf[12..16].try_into().unwrap()),
-                frame_rate: f32::from_be_bytes(header_buf[16..20].try_into().unwrap()),
+            let file = File::open(path).map_err(|e| Error::from_io(e, 0))?;
+            *self = Nif::read_from(BufReader::new(file))?;
+            Ok(())
+        }
+
+        //Reads a whole NIF stream. Unbuffered readers should be wrapped in a BufReader.
+        pub fn read_from<R: Read>(reader: R) -> Result<Nif> {
+            let mut reader = NifReader::new(reader)?;
+            let mut nif = Nif {
+                version: reader.version(),
+                features: reader.features(),
+                header: *reader.header(),
+                frames: Vec::new(),
+                levels: Vec::new(),
+                metadata: reader.metadata().clone(),
+                color_space: reader.color_space(),
+                icc_profile: reader.icc_profile().map(<[u8]>::to_vec),
+                exif: reader.exif().map(<[u8]>::to_vec),
+                xmp: reader.xmp().map(<[u8]>::to_vec),
             };
-            self.header = header;
-            if feature_flags & FEATURE_FLAGS_COMPRESSION != 0 {
-                self.read_compressed(&header, &mut buf)
-            } else {
-                self.read_uncompressed(&header, &mut buf)
+            nif.frames = reader.read_remaining()?;
+            nif.levels = reader.take_levels();
+            nif.header.frame_count = nif.frames.len() as u32;
+            Ok(nif)
+        }
+
+        //Mipmap level `n` of the image, level 0 being the first frame. Levels
+        //above 0 are only there after reading a file with
+        //FEATURE_FLAGS_MIPMAPS or calling generate_levels.
+        pub fn level(&self, n: u32) -> Option<&Frame> {
+            match n {
+                0 => self.frames.first(),
+                _ => self.levels.get(n as usize - 1),
             }
         }
+        //Computes the mipmap levels of the first frame. Writing with
+        //FEATURE_FLAGS_MIPMAPS computes them again with the filter of the
+        //WriteOptions.
+        pub fn generate_levels(&mut self, filter: MipmapFilter) {
+            self.levels = match self.frames.first() {
+                Some(frame) => mipmap::levels(&self.header, &frame.data, filter)
+                    .into_iter()
+                    .map(Frame::from)
+                    .collect(),
+                None => Vec::new(),
+            };
+        }
 
-        pub fn read_uncompressed(
-            &mut self,
-            header: &Header,
-            buf: &mut BufReader<File>,
-        ) -> Result<()> {
+        //Title, author and other key/value pairs stored with the file. Version
+        //0.1 files cannot hold them.
+        pub fn metadata(&self) -> &Metadata {
+            &self.metadata
+        }
+        pub fn metadata_mut(&mut self) -> &mut Metadata {
+            &mut self.metadata
+        }
+
+        //The color space of the pixel values, sRGB unless set otherwise. Version
+        //0.1 files cannot hold other color spaces.
+        pub fn color_space(&self) -> ColorSpace {
+            self.color_space
+        }
+        pub fn set_color_space(&mut self, color_space: ColorSpace) {
+            self.color_space = color_space;
+        }
+        //The ICC profile embedded in the file, if any. Version 0.1 files
+        //cannot hold one.
+        pub fn icc_profile(&self) -> Option<&[u8]> {
+            self.icc_profile.as_deref()
+        }
+        pub fn set_icc_profile(&mut self, profile: Option<Vec<u8>>) {
+            self.icc_profile = profile;
+        }
+        //EXIF data in TIFF layout and an XMP packet, carried as is. Version
+        //0.1 files cannot hold them.
+        pub fn exif(&self) -> Option<&[u8]> {
+            self.exif.as_deref()
+        }
+        pub fn set_exif(&mut self, exif: Option<Vec<u8>>) {
+            self.exif = exif;
+        }
+        pub fn xmp(&self) -> Option<&[u8]> {
+            self.xmp.as_deref()
+        }
+        pub fn set_xmp(&mut self, xmp: Option<Vec<u8>>) {
+            self.xmp = xmp;
+        }
+        //How the image must be turned to display it upright, taken from the
+        //EXIF data. Normal without EXIF data or an orientation tag.
+        pub fn orientation(&self) -> Orientation {
+            self.exif
+                .as_deref()
+                .and_then(exif::orientation)
+                .unwrap_or_default()
+        }
+
+        pub fn from_bytes(bytes: &[u8]) -> Result<Nif> {
+            Nif::read_from(bytes)
+        }
+
+        pub fn read_uncompressed<R: Read>(&mut self, header: &Header, buf: &mut R) -> Result<()> {
+            self.read_payload(header, self.features & FEATURE_FLAGS_INTERLACE, buf)
+        }
+
+        pub fn read_compressed<R: Read>(&mut self, header: &Header, buf: &mut R) -> Result<()> {
+            //the filter and interlace flags come from the feature flags read with
+            //the header
+            let features = FEATURE_FLAGS_COMPRESSION
+                | (self.features & (FEATURE_FLAGS_FILTER | FEATURE_FLAGS_INTERLACE));
+            self.read_payload(header, features, buf)
+        }
+
+        //reads the frames following the header into self.frames
+        fn read_payload<R: Read>(&mut self, header: &Header, features: u32, buf: R) -> Result<()> {
+            let buf = Counting::at(buf, PAYLOAD_OFFSET);
+            let mut reader = NifReader::with_payload(buf, self.version, features, *header)?;
+            self.frames.append(&mut reader.read_remaining()?);
             Ok(())
         }
 
-        pub fn read_compressed(
-            &mut self,
-            header: &Header,
-            buf: &mut BufReader<File>,
-        ) -> Result<()> {
-            let mut dec = GzDecoder::new(buf);
+        //`options` is either the feature flags or a WriteOptions with a
+        //compression level
+        pub fn write(&self, path: &Path, options: impl Into<WriteOptions>) -> Result<()> {
+            let file = File::create(path).map_err(|e| Error::from_io(e, 0))?;
+            self.write_to(BufWriter::new(file), options)
+        }
 
-            let bit_depth = match header.pixel_format {
-                Pixel::RGBA8888(_) => 4,
-                Pixel::RGB888(_) => 4,
-                Pixel::RGBA4444(_) => 2,
-                Pixel::RGB444(_) => 2,
+        //Writes the whole NIF stream and flushes the writer.
+        pub fn write_to<W: Write>(
+            &self,
+            writer: W,
+            options: impl Into<WriteOptions>,
+        ) -> Result<()> {
+            let options = options.into();
+            check_features(options.features)?;
+            if self.header.frame_count as usize != self.frames.len() {
+                return Err(Error::FrameCountMismatch {
+                    offset: 24,
+                    expected: self.header.frame_count,
+                    found: self.frames.len(),
+                });
+            }
+            let mut buf = Counting::new(writer);
+            let prefix = Prefix {
+                metadata: self.metadata.clone(),
+                color_space: self.color_space,
+                icc_profile: self.icc_profile.clone(),
+                exif: self.exif.clone(),
+                xmp: self.xmp.clone(),
+                //the first chunk after the header
+                timestamps: time::collect(&self.frames, chunks_offset(self.version))?,
+                ..Prefix::new(self.version, options.features, self.header)
             };
-            let data_per_frame = header.width as usize * header.height as usize * bit_depth;
-
-            for _ in 0..header.frame_count {
-                let mut frame_data = vec![0; data_per_frame];
-                dec.read_exact(&mut frame_data).unwrap();
-                self.frames.push(Frame { data: frame_data });
+            write_prefix(&mut buf, &prefix)?;
+            if !is_chunked(self.version) {
+                return self.write_payload(options, buf);
             }
+            //the frames are encoded first, the DATA chunk starts with their size
+            let mut payload = Vec::new();
+            let payload_start = buf.position() + chunks::CHUNK_HEADER_SIZE;
+            self.write_payload(options, Counting::at(&mut payload, payload_start))?;
+            chunks::write_chunk(&mut buf, chunks::CHUNK_DATA, &payload)?;
+            let position = buf.position();
+            buf.flush().map_err(|e| Error::from_io(e, position))
+        }
+
+        pub fn to_bytes(&self, options: impl Into<WriteOptions>) -> Result<Vec<u8>> {
+            let mut bytes = Vec::new();
+            self.write_to(&mut bytes, options)?;
+            Ok(bytes)
+        }
+
+        pub fn new_empty_frame(&mut self) {
+            self.header.frame_count += 1;
+            let hd = self.header;
+            let frame = Frame::new(hd);
+            self.frames.push(frame);
+        }
+        pub fn write_compressed<W: Write>(&self, buf: &mut W) -> Result<()> {
+            let features = FEATURE_FLAGS_COMPRESSION
+                | (self.features & (FEATURE_FLAGS_FILTER | FEATURE_FLAGS_INTERLACE));
+            self.write_payload(features.into(), Counting::at(buf, PAYLOAD_OFFSET))
+        }
+
+        pub fn write_uncompressed<W: Write>(&self, buf: &mut W) -> Result<()> {
+            let features = self.features & FEATURE_FLAGS_INTERLACE;
+            self.write_payload(features.into(), Counting::at(buf, PAYLOAD_OFFSET))
+        }
+
+        //writes the frames following the header
+        fn write_payload<W: Write>(&self, options: WriteOptions, buf: Counting<W>) -> Result<()> {
+            let mut writer = NifWriter::with_sink(buf, self.header, options)?;
+            writer.push_frames(&self.frames)?;
+            writer.finish()?;
             Ok(())
         }
+    }
+
+    //everything stored before the frames
+    struct Prefix {
+        version: u32,
+        features: u32,
+        header: Header,
+        //the ancillary chunks below are never there in version 0.1
+        metadata: Metadata,
+        color_space: ColorSpace,
+        icc_profile: Option<Vec<u8>>,
+        exif: Option<Vec<u8>>,
+        xmp: Option<Vec<u8>>,
+        timestamps: Option<time::Timestamps>,
+    }
+
+    impl Prefix {
+        fn new(version: u32, features: u32, header: Header) -> Self {
+            Prefix {
+                version,
+                features,
+                header,
+                metadata: Metadata::new(),
+                color_space: ColorSpace::SRGB,
+                icc_profile: None,
+                exif: None,
+                xmp: None,
+                timestamps: None,
+            }
+        }
+    }
+
+    //reads the magic number, version, feature flags and header. In version
+    //0.2 streams the chunks before the DATA chunk are read too, and reads
+    //from `buf` end with the DATA chunk
+    fn read_prefix<R: Read>(buf: &mut Counting<R>) -> Result<Prefix> {
+        let mut magic_number = [0; 4];
+        buf.read_checked(&mut magic_number)?;
+        let magic_number = u32::from_be_bytes(magic_number);
+        if magic_number != MAGIC_NUMBER {
+            return Err(Error::BadMagic {
+                offset: 0,
+                found: magic_number,
+            });
+        }
 
-        pub fn write(&self, path: &Path, features: u32) -> std::io::Result<()> {
-            let mut buf = std::io::BufWriter::new(std::fs::File::create(path).unwrap());
-            buf.write_all(&MAGIC_NUMBER.to_be_bytes()).unwrap();
-            //write four empty bytes for feature flags
-            //write_version
-            buf.write_all(&self.version.to_be_bytes()).unwrap();
-            //write features
-            buf.write_all(&features.to_be_bytes()).unwrap();
-
-            //write rest of header
-            let mut header_buf = [0; HEADER_SIZE];
-            header_buf[0..4].copy_from_slice(&self.header.width.to_be_bytes());
-            header_buf[4..8].copy_from_slice(&self.header.height.to_be_bytes());
-            match self.header.pixel_format {
-                Pixel::RGBA8888(_) => {
-                    header_buf[8..12].copy_from_slice(&0_u32.to_be_bytes());
+        let mut version_buf = [0; 4];
+        buf.read_checked(&mut version_buf)?;
+        let version = u32::from_be_bytes(version_buf);
+        if version > CURRENT_VERSION {
+            return Err(Error::UnsupportedVersion { offset: 4, version });
+        }
+        let mut feature_flags = [0; 4];
+        buf.read_checked(&mut feature_flags)?;
+        let feature_flags = u32::from_be_bytes(feature_flags);
+        //unknown ancillary features are read as if they were not set
+        let feature_flags =
+            feature_flags & (SUPPORTED_FEATURE_FLAGS | !FEATURE_FLAGS_ANCILLARY_MASK);
+        check_features(feature_flags)?;
+
+        let mut header_buf = [0; HEADER_SIZE_0_3];
+        let header_buf = &mut header_buf[..header_size(version)];
+        if !is_chunked(version) {
+            buf.read_checked(header_buf)?;
+            let header = parse_header(header_buf, 12)?;
+            return Ok(Prefix::new(version, feature_flags, header));
+        }
+        let offset = buf.position();
+        let header = match chunks::read_chunk_header(buf)? {
+            Some((chunks::CHUNK_HEAD, length)) if length == header_buf.len() as u64 => {
+                buf.read_checked(header_buf)?;
+                parse_header(header_buf, offset + chunks::CHUNK_HEADER_SIZE)?
+            }
+            Some(_) => {
+                return Err(Error::Corrupt {
+                    offset,
+                    reason: "the stream does not start with a header chunk",
+                })
+            }
+            None => return Err(Error::Truncated { offset }),
+        };
+        let mut prefix = Prefix::new(version, feature_flags, header);
+        //chunks before the frames
+        loop {
+            let offset = buf.position();
+            let Some((tag, length)) = chunks::read_chunk_header(buf)? else {
+                return Err(Error::Truncated { offset });
+            };
+            match tag {
+                chunks::CHUNK_DATA => {
+                    if length != chunks::LENGTH_UNKNOWN {
+                        let end = buf.position().checked_add(length);
+                        buf.set_end(Some(end.ok_or(Error::Truncated { offset })?));
+                    }
+                    return Ok(prefix);
+                }
+                chunks::CHUNK_HEAD => {
+                    return Err(Error::Corrupt {
+                        offset,
+                        reason: "misplaced chunk",
+                    })
                 }
-                Pixel::RGB888(_) => {
-                    header_buf[8..12].copy_from_slice(&1_u32.to_be_bytes());
+                chunks::CHUNK_META => {
+                    let data = chunks::read_chunk_data(buf, length)?;
+                    let start = offset + chunks::CHUNK_HEADER_SIZE;
+                    prefix.metadata = metadata::decode(&data, start)?;
                 }
-                Pixel::RGBA4444(_) => {
-                    header_buf[8..12].copy_from_slice(&2_u32.to_be_bytes());
+                chunks::CHUNK_COLOR => {
+                    let data = chunks::read_chunk_data(buf, length)?;
+                    let start = offset + chunks::CHUNK_HEADER_SIZE;
+                    prefix.color_space = color::decode(&data, start)?;
                 }
-                Pixel::RGB444(_) => {
-                    header_buf[8..12].copy_from_slice(&3_u32.to_be_bytes());
+                chunks::CHUNK_ICC => {
+                    prefix.icc_profile = Some(chunks::read_chunk_data(buf, length)?);
                 }
+                chunks::CHUNK_EXIF => prefix.exif = Some(chunks::read_chunk_data(buf, length)?),
+                chunks::CHUNK_XMP => prefix.xmp = Some(chunks::read_chunk_data(buf, length)?),
+                chunks::CHUNK_TIME => {
+                    let count = prefix.header.frame_count;
+                    prefix.timestamps = Some(read_timestamps(buf, length, offset, count)?);
+                }
+                _ if chunks::is_ancillary(tag) => chunks::skip_chunk_data(buf, length)?,
+                _ => return Err(Error::UnknownChunk { offset, tag }),
             }
-            header_buf[12..16].copy_from_slice(&self.header.frame_count.to_be_bytes());
-            header_buf[16..20].copy_from_slice(&self.header.frame_rate.to_be_bytes());
-            buf.write_all(&header_buf).unwrap();
+        }
+    }
 
-            if features & FEATURE_FLAGS_COMPRESSION != 0 {
-                self.write_compressed(&mut buf)
-            } else {
-                self.write_uncompressed(&mut buf)
+    //reads the data of a `time` chunk starting at `offset`, which must hold a
+    //timestamp for each of `frame_count` frames unless that is unknown
+    fn read_timestamps<R: Read>(
+        buf: &mut Counting<R>,
+        length: u64,
+        offset: u64,
+        frame_count: u32,
+    ) -> Result<time::Timestamps> {
+        let data = chunks::read_chunk_data(buf, length)?;
+        let start = offset + chunks::CHUNK_HEADER_SIZE;
+        let timestamps = time::decode(&data, start)?;
+        if frame_count != FRAME_COUNT_UNKNOWN && timestamps.ticks.len() != frame_count as usize {
+            return Err(Error::Corrupt {
+                offset: start,
+                reason: "wrong number of timestamps",
+            });
+        }
+        Ok(timestamps)
+    }
+
+    //`offset` is where the header starts in the stream, the version is told
+    //by the size of `header_buf`
+    fn parse_header(header_buf: &[u8], offset: u64) -> Result<Header> {
+        let pixel_format = u32::from_be_bytes(header_buf[8..12].try_into().unwrap());
+        let rate = match header_buf.len() {
+            HEADER_SIZE => {
+                Rational::from_f32(f32::from_be_bytes(header_buf[16..20].try_into().unwrap()))
             }
+            _ => Rational::new(
+                u32::from_be_bytes(header_buf[16..20].try_into().unwrap()),
+                u32::from_be_bytes(header_buf[20..24].try_into().unwrap()),
+            ),
+        };
+        if rate.den == 0 {
+            return Err(Error::Corrupt {
+                offset: offset + 20,
+                reason: "the frame rate has a zero denominator",
+            });
         }
-        pub fn new_empty_frame(&mut self) {
-            self.header.frame_count += 1;
-            let hd = self.header;
-            let frame = Frame::new(hd);
-            self.frames.push(frame);
+        Ok(Header {
+            width: u32::from_be_bytes(header_buf[0..4].try_into().unwrap()),
+            height: u32::from_be_bytes(header_buf[4..8].try_into().unwrap()),
+            pixel_format: match pixel_format {
+                0 => Pixel::RGBA8888(0.into()),
+                1 => Pixel::RGB888(0.into()),
+                2 => Pixel::RGBA4444(0.into()),
+                3 => Pixel::RGB444(0.into()),
+                _ => {
+                    return Err(Error::UnknownPixelFormat {
+                        offset: offset + 8,
+                        format: pixel_format,
+                    })
+                }
+            },
+            frame_count: u32::from_be_bytes(header_buf[12..16].try_into().unwrap()),
+            rate,
+        })
+    }
+
+    fn header_size(version: u32) -> usize {
+        match version {
+            VERSION_0_3.. => HEADER_SIZE_0_3,
+            _ => HEADER_SIZE,
         }
-        pub fn write_compressed(&self, buf: &mut BufWriter<File>) -> Result<()> {
-            use flate2::*;
-            let mut encoder = GzEncoder::new(buf, Compression::default());
-            for frame in &self.frames {
-                encoder.write_all(&frame.data)?;
-            }
-            Ok(())
+    }
+
+    //offset of the first chunk after the HEAD chunk in version 0.2 and up
+    fn chunks_offset(version: u32) -> u64 {
+        12 + chunks::CHUNK_HEADER_SIZE + header_size(version) as u64
+    }
+
+    //true for versions that store the header and payload in chunks
+    fn is_chunked(version: u32) -> bool {
+        version >= VERSION_0_2
+    }
+
+    //rejects unknown feature flags and codecs missing from this build
+    fn check_features(features: u32) -> Result<()> {
+        if features & !SUPPORTED_FEATURE_FLAGS != 0 {
+            return Err(Error::UnknownFeatureFlags {
+                offset: 8,
+                flags: features & !SUPPORTED_FEATURE_FLAGS,
+            });
+        }
+        if features & FEATURE_FLAGS_FILTER != 0 && features & FEATURE_FLAGS_COMPRESSION == 0 {
+            return Err(Error::Unsupported {
+                offset: 8,
+                reason: "row filters require compression",
+            });
         }
+        if features & FEATURE_FLAGS_RLE != 0 && features & FEATURE_FLAGS_COMPRESSION != 0 {
+            return Err(Error::Unsupported {
+                offset: 8,
+                reason: "run-length encoding cannot be combined with compression",
+            });
+        }
+        let needs_records = FEATURE_FLAGS_DELTA
+            | FEATURE_FLAGS_DIRTY_RECTS
+            | FEATURE_FLAGS_RLE
+            | FEATURE_FLAGS_TILES
+            | FEATURE_FLAGS_MIPMAPS;
+        if features & needs_records != 0 && features & FEATURE_FLAGS_FRAME_INDEX == 0 {
+            return Err(Error::Unsupported {
+                offset: 8,
+                reason: "the feature flags require frame records",
+            });
+        }
+        if features & FEATURE_FLAGS_TILES != 0
+            && features & (FEATURE_FLAGS_DELTA | FEATURE_FLAGS_DIRTY_RECTS) != 0
+        {
+            return Err(Error::Unsupported {
+                offset: 8,
+                reason: "tiled frames cannot depend on the frame before them",
+            });
+        }
+        codec::for_features(features)?;
+        Ok(())
+    }
+
+    fn write_prefix<W: Write>(buf: &mut Counting<W>, prefix: &Prefix) -> Result<()> {
+        let Prefix {
+            version,
+            features,
+            ref header,
+            ref metadata,
+            color_space,
+            ref icc_profile,
+            ref exif,
+            ref xmp,
+            ref timestamps,
+        } = *prefix;
+        if version > CURRENT_VERSION {
+            return Err(Error::UnsupportedVersion { offset: 4, version });
+        }
+        if version >= VERSION_0_3 && header.rate.den == 0 {
+            return Err(Error::Unsupported {
+                offset: chunks_offset(version) - 4,
+                reason: "the frame rate has a zero denominator",
+            });
+        }
+        if !is_chunked(version) && !metadata.is_empty() {
+            return Err(Error::Unsupported {
+                offset: 4,
+                reason: "metadata requires version 0.2",
+            });
+        }
+        if !is_chunked(version) && (color_space != ColorSpace::SRGB || icc_profile.is_some()) {
+            return Err(Error::Unsupported {
+                offset: 4,
+                reason: "color spaces other than sRGB require version 0.2",
+            });
+        }
+        if !is_chunked(version) && (exif.is_some() || xmp.is_some()) {
+            return Err(Error::Unsupported {
+                offset: 4,
+                reason: "EXIF and XMP data require version 0.2",
+            });
+        }
+        if !is_chunked(version) && timestamps.is_some() {
+            return Err(Error::Unsupported {
+                offset: 4,
+                reason: "timestamps require version 0.2",
+            });
+        }
+        buf.write_checked(&MAGIC_NUMBER.to_be_bytes())?;
+        //write_version
+        buf.write_checked(&version.to_be_bytes())?;
+        //write features
+        buf.write_checked(&features.to_be_bytes())?;
 
-        pub fn write_uncompressed(&self, buf: &mut BufWriter<File>) -> Result<()> {
-            for frame in &self.frames {
-                buf.write_all(&frame.data)?;
+        //write rest of header
+        let mut header_buf = [0; HEADER_SIZE_0_3];
+        let header_buf = &mut header_buf[..header_size(version)];
+        header_buf[0..4].copy_from_slice(&header.width.to_be_bytes());
+        header_buf[4..8].copy_from_slice(&header.height.to_be_bytes());
+        match header.pixel_format {
+            Pixel::RGBA8888(_) => {
+                header_buf[8..12].copy_from_slice(&0_u32.to_be_bytes());
+            }
+            Pixel::RGB888(_) => {
+                header_buf[8..12].copy_from_slice(&1_u32.to_be_bytes());
+            }
+            Pixel::RGBA4444(_) => {
+                header_buf[8..12].copy_from_slice(&2_u32.to_be_bytes());
+            }
+            Pixel::RGB444(_) => {
+                header_buf[8..12].copy_from_slice(&3_u32.to_be_bytes());
             }
-            Ok(())
         }
+        header_buf[12..16].copy_from_slice(&header.frame_count.to_be_bytes());
+        if version < VERSION_0_3 {
+            header_buf[16..20].copy_from_slice(&header.frame_rate().to_be_bytes());
+        } else {
+            header_buf[16..20].copy_from_slice(&header.rate.num.to_be_bytes());
+            header_buf[20..24].copy_from_slice(&header.rate.den.to_be_bytes());
+        }
+        if !is_chunked(version) {
+            return buf.write_checked(header_buf);
+        }
+        chunks::write_chunk(buf, chunks::CHUNK_HEAD, header_buf)?;
+        if color_space != ColorSpace::SRGB {
+            chunks::write_chunk(buf, chunks::CHUNK_COLOR, &color::encode(color_space))?;
+        }
+        if let Some(profile) = icc_profile {
+            chunks::write_chunk(buf, chunks::CHUNK_ICC, profile)?;
+        }
+        if let Some(exif) = exif {
+            chunks::write_chunk(buf, chunks::CHUNK_EXIF, exif)?;
+        }
+        if let Some(xmp) = xmp {
+            chunks::write_chunk(buf, chunks::CHUNK_XMP, xmp)?;
+        }
+        if !metadata.is_empty() {
+            chunks::write_chunk(buf, chunks::CHUNK_META, &metadata::encode(metadata))?;
+        }
+        if let Some(timestamps) = timestamps {
+            chunks::write_chunk(buf, chunks::CHUNK_TIME, &time::encode(timestamps))?;
+        }
+        Ok(())
+    }
+
+    //like Header::frame_size, but reports the overflow as an error
+    fn frame_size(header: &Header) -> Result<usize> {
+        header.frame_size().ok_or(Error::DimensionOverflow {
+            offset: 12,
+            width: header.width,
+            height: header.height,
+        })
+    }
+
+    //resizes `frame` to the frame size of `header`, filling it with zeros.
+    //The header may be corrupt, so a size the allocator refuses is an error
+    //rather than an abort
+    fn resize_frame(frame: &mut Vec<u8>, header: &Header) -> Result<()> {
+        let len = frame_size(header)?;
+        frame
+            .try_reserve_exact(len.saturating_sub(frame.len()))
+            .map_err(|_| Error::DimensionOverflow {
+                offset: 12,
+                width: header.width,
+                height: header.height,
+            })?;
+        frame.resize(len, 0);
+        Ok(())
     }
 }
 
@@ -443,7 +1101,14 @@ mod test_super {
 
     use rand::Rng;
 
-    use crate::nif::{Header, Nif, Pixel, Pixel32U, FEATURE_FLAGS_COMPRESSION};
+    use crate::nif::{
+        Error, Header, Nif, NifReader, NifWriter, Pixel, Pixel16U, Pixel32U, PixelIterator,
+        Rational, WriteOptions, FEATURE_FLAGS_CODEC_DEFLATE, FEATURE_FLAGS_CODEC_LZ4,
+        FEATURE_FLAGS_CODEC_MASK, FEATURE_FLAGS_CODEC_QOI, FEATURE_FLAGS_CODEC_ZSTD,
+        FEATURE_FLAGS_COMPRESSION, FEATURE_FLAGS_DELTA, FEATURE_FLAGS_DIRTY_RECTS,
+        FEATURE_FLAGS_FILTER, FEATURE_FLAGS_FRAME_INDEX, FEATURE_FLAGS_MIPMAPS, FEATURE_FLAGS_RLE,
+        FEATURE_FLAGS_TILES, FRAME_COUNT_UNKNOWN, VERSION_0_1,
+    };
     #[test]
     fn test_access_pixels() {
         let mut nif = Nif::new(Header {
@@ -451,7 +1116,7 @@ mod test_super {
             height: 10,
             pixel_format: Pixel::RGBA8888(0.into()),
             frame_count: 0,
-            frame_rate: 0.0,
+            rate: Rational::new(0, 1),
         });
 
         nif.new_empty_frame();
@@ -490,7 +1155,7 @@ mod test_super {
             height: 400,
             pixel_format: Pixel::RGBA8888(0.into()),
             frame_count: 0,
-            frame_rate: 0.0,
+            rate: Rational::new(0, 1),
         });
 
         nif.new_empty_frame();
@@ -523,12 +1188,11 @@ mod test_super {
             assert_eq!(nif.header.height, nif_read.header.height);
             assert_eq!(nif.header.pixel_format, nif_read.header.pixel_format);
             assert_eq!(nif.header.frame_count, nif_read.header.frame_count);
-            assert_eq!(nif.header.frame_rate, nif_read.header.frame_rate);
+            assert_eq!(nif.header.frame_rate(), nif_read.header.frame_rate());
         }
         //compare the frames
-        for frame_pair in nif.get_frames().iter().zip(nif_read.get_frames().iter()) {
-            assert_eq!(&frame_pair.0, &frame_pair.1);
-        }
+        assert_eq!(nif.get_frames().len(), nif_read.get_frames().len());
+        assert_eq!(nif.get_frames(), nif_read.get_frames());
 
         //compressed
         nif.write(Path::new("test_comp.nif"), FEATURE_FLAGS_COMPRESSION)
@@ -543,15 +1207,10 @@ mod test_super {
         assert_eq!(nif.header.height, nif_read_comp.header.height);
         assert_eq!(nif.header.pixel_format, nif_read_comp.header.pixel_format);
         assert_eq!(nif.header.frame_count, nif_read_comp.header.frame_count);
-        assert_eq!(nif.header.frame_rate, nif_read_comp.header.frame_rate);
+        assert_eq!(nif.header.frame_rate(), nif_read_comp.header.frame_rate());
         //compare the frames
-        for frame_pair in nif
-            .get_frames()
-            .iter()
-            .zip(nif_read_comp.get_frames().iter())
-        {
-            assert_eq!(&frame_pair.0, &frame_pair.1);
-        }
+        assert_eq!(nif.get_frames().len(), nif_read_comp.get_frames().len());
+        assert_eq!(nif.get_frames(), nif_read_comp.get_frames());
     }
     #[test]
     fn test_serialize_random() {
@@ -560,7 +1219,7 @@ mod test_super {
             height: 400,
             pixel_format: Pixel::RGBA8888(0.into()),
             frame_count: 0,
-            frame_rate: 0.0,
+            rate: Rational::new(0, 1),
         });
         let mut rng = rand::thread_rng();
 
@@ -594,12 +1253,11 @@ mod test_super {
             assert_eq!(nif.header.height, nif_read.header.height);
             assert_eq!(nif.header.pixel_format, nif_read.header.pixel_format);
             assert_eq!(nif.header.frame_count, nif_read.header.frame_count);
-            assert_eq!(nif.header.frame_rate, nif_read.header.frame_rate);
+            assert_eq!(nif.header.frame_rate(), nif_read.header.frame_rate());
         }
         //compare the frames
-        for frame_pair in nif.get_frames().iter().zip(nif_read.get_frames().iter()) {
-            assert_eq!(&frame_pair.0, &frame_pair.1);
-        }
+        assert_eq!(nif.get_frames().len(), nif_read.get_frames().len());
+        assert_eq!(nif.get_frames(), nif_read.get_frames());
 
         //compressed
         nif.write(Path::new("test_comp_rng.nif"), FEATURE_FLAGS_COMPRESSION)
@@ -613,14 +1271,1809 @@ mod test_super {
         assert_eq!(nif.header.height, nif_read_comp.header.height);
         assert_eq!(nif.header.pixel_format, nif_read_comp.header.pixel_format);
         assert_eq!(nif.header.frame_count, nif_read_comp.header.frame_count);
-        assert_eq!(nif.header.frame_rate, nif_read_comp.header.frame_rate);
+        assert_eq!(nif.header.frame_rate(), nif_read_comp.header.frame_rate());
         //compare the frames
-        for frame_pair in nif
-            .get_frames()
-            .iter()
-            .zip(nif_read_comp.get_frames().iter())
-        {
-            assert_eq!(&frame_pair.0, &frame_pair.1);
+        assert_eq!(nif.get_frames().len(), nif_read_comp.get_frames().len());
+        assert_eq!(nif.get_frames(), nif_read_comp.get_frames());
+    }
+    #[test]
+    fn test_serialize_uncompressed_multi_frame() {
+        let mut nif = Nif::new(Header {
+            width: 16,
+            height: 8,
+            pixel_format: Pixel::RGBA4444(0.into()),
+            frame_count: 0,
+            rate: Rational::new(30, 1),
+        });
+        for f in 0..3 {
+            nif.new_empty_frame();
+            let frame = nif.get_frame(f).unwrap();
+            for (i, byte) in frame.data.iter_mut().enumerate() {
+                *byte = (i as u32 + f) as u8;
+            }
+        }
+        let path = std::env::temp_dir().join("nif_test_multi_frame.nif");
+        nif.write(&path, 0).unwrap();
+
+        let mut nif_read = Nif::new_default();
+        nif_read.read_from_file(&path).unwrap();
+        assert_eq!(nif_read.header.frame_count, 3);
+        assert_eq!(nif_read.get_frames().len(), 3);
+        assert_eq!(nif.get_frames(), nif_read.get_frames());
+        std::fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn test_reject_bad_payload_length() {
+        let mut nif = Nif::new(Header {
+            width: 4,
+            height: 4,
+            pixel_format: Pixel::RGBA8888(0.into()),
+            frame_count: 0,
+            rate: Rational::new(0, 1),
+        });
+        //the payload runs to the end of a version 0.1 file
+        nif.version = VERSION_0_1;
+        nif.new_empty_frame();
+        nif.new_empty_frame();
+        let path = std::env::temp_dir().join("nif_test_bad_payload.nif");
+        nif.write(&path, 0).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+
+        //missing the last byte of the second frame
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        match Nif::new_default().read_from_file(&path) {
+            Err(Error::Truncated { offset }) => assert_eq!(offset, bytes.len() as u64 - 1),
+            other => panic!("expected Truncated, got {:?}", other.err()),
+        }
+
+        //one byte more than the header announces
+        let mut long = bytes.clone();
+        long.push(0);
+        std::fs::write(&path, &long).unwrap();
+        match Nif::new_default().read_from_file(&path) {
+            Err(Error::TrailingData { offset }) => assert_eq!(offset, bytes.len() as u64),
+            other => panic!("expected TrailingData, got {:?}", other.err()),
         }
+
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(Nif::new_default().read_from_file(&path).is_ok());
+        std::fs::remove_file(&path).unwrap();
+
+        //older writers stored a frame count of 0 for a single image
+        let frame_len = (bytes.len() - 32) / 2;
+        let mut legacy = bytes[..32 + frame_len].to_vec();
+        legacy[24..28].copy_from_slice(&0_u32.to_be_bytes());
+        let read = Nif::from_bytes(&legacy).unwrap();
+        assert_eq!(read.get_frames().len(), 1);
+        assert_eq!(read.header.frame_count, 1);
+        assert!(Nif::from_bytes(&legacy[..32])
+            .unwrap()
+            .get_frames()
+            .is_empty());
+        legacy.extend_from_slice(&bytes[32 + frame_len..]);
+        assert!(matches!(
+            Nif::from_bytes(&legacy),
+            Err(Error::TrailingData { offset }) if offset == 32 + frame_len as u64
+        ));
+    }
+    #[test]
+    fn test_reject_malformed_header() {
+        let mut nif = Nif::new(Header {
+            width: 2,
+            height: 2,
+            pixel_format: Pixel::RGB888(0.into()),
+            frame_count: 0,
+            rate: Rational::new(0, 1),
+        });
+        nif.version = VERSION_0_1;
+        nif.new_empty_frame();
+        let path = std::env::temp_dir().join("nif_test_malformed_header.nif");
+        nif.write(&path, 0).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        let read_patched = |at: usize, value: u32| {
+            let mut patched = bytes.clone();
+            patched[at..at + 4].copy_from_slice(&value.to_be_bytes());
+            std::fs::write(&path, &patched).unwrap();
+            Nif::new_default().read_from_file(&path).unwrap_err()
+        };
+
+        let err = read_patched(0, 0x89504E47);
+        assert!(matches!(
+            err,
+            Error::BadMagic {
+                offset: 0,
+                found: 0x89504E47
+            }
+        ));
+        let err = read_patched(4, 0x00040000);
+        assert!(matches!(err, Error::UnsupportedVersion { offset: 4, .. }));
+        let err = read_patched(8, 0x1000);
+        assert!(matches!(
+            err,
+            Error::UnknownFeatureFlags {
+                offset: 8,
+                flags: 0x1000
+            }
+        ));
+        let err = read_patched(20, 7);
+        assert!(matches!(
+            err,
+            Error::UnknownPixelFormat {
+                offset: 20,
+                format: 7
+            }
+        ));
+        assert_eq!(err.offset(), 20);
+        assert!(err.to_string().contains("at byte 20"));
+
+        std::fs::write(&path, &bytes[..10]).unwrap();
+        let err = Nif::new_default().read_from_file(&path).unwrap_err();
+        assert!(matches!(err, Error::Truncated { offset: 10 }));
+        std::fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn test_write_rejects_inconsistent_frames() {
+        let mut nif = Nif::new(Header {
+            width: 2,
+            height: 2,
+            pixel_format: Pixel::RGBA8888(0.into()),
+            frame_count: 0,
+            rate: Rational::new(0, 1),
+        });
+        nif.new_empty_frame();
+        let path = std::env::temp_dir().join("nif_test_inconsistent_frames.nif");
+
+        nif.get_frame(0).unwrap().data.pop();
+        let err = nif.write(&path, 0).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::FrameSizeMismatch {
+                offset: 60,
+                index: 0,
+                expected: 16,
+                found: 15
+            }
+        ));
+
+        nif.header.frame_count = 2;
+        let err = nif.write(&path, 0).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::FrameCountMismatch {
+                expected: 2,
+                found: 1,
+                ..
+            }
+        ));
+        let _ = std::fs::remove_file(&path);
+    }
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut nif = Nif::new(Header {
+            width: 3,
+            height: 5,
+            pixel_format: Pixel::RGB444(0.into()),
+            frame_count: 0,
+            rate: Rational::new(24, 1),
+        });
+        for f in 0..4 {
+            nif.new_empty_frame();
+            let frame = nif.get_frame(f).unwrap();
+            for (i, byte) in frame.data.iter_mut().enumerate() {
+                *byte = (i as u32 * 7 + f) as u8;
+            }
+        }
+        for features in [0, FEATURE_FLAGS_COMPRESSION] {
+            let bytes = nif.to_bytes(features).unwrap();
+            let nif_read = Nif::from_bytes(&bytes).unwrap();
+            assert_eq!(nif_read.features, features);
+            assert_eq!(nif_read.header.frame_count, 4);
+            assert_eq!(nif_read.header.frame_rate(), 24.0);
+            assert_eq!(nif.get_frames(), nif_read.get_frames());
+
+            //any reader works, e.g. one delivering the data in small pieces
+            let nif_read = Nif::read_from(Trickle(&bytes)).unwrap();
+            assert_eq!(nif.get_frames(), nif_read.get_frames());
+        }
+    }
+    //a reader that hands out at most seven bytes per call
+    struct Trickle<'a>(&'a [u8]);
+    impl std::io::Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(7).min(self.0.len());
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+    //Builds a single frame image from `pixels` and checks it against a golden
+    //file produced independently from the spec.
+    fn check_golden(golden: &[u8], width: u32, height: u32, frame_rate: f32, pixels: &[Pixel]) {
+        let mut nif = Nif::new(Header {
+            width,
+            height,
+            pixel_format: pixels[0],
+            frame_count: 0,
+            rate: Rational::from_f32(frame_rate),
+        });
+        //the golden files use the version 0.1 layout
+        nif.version = VERSION_0_1;
+        nif.new_empty_frame();
+        let hd = nif.header;
+        let frame = nif.get_frame(0).unwrap();
+        for (i, pixel) in pixels.iter().enumerate() {
+            frame.set_pixel(i as u32 % width, i as u32 / width, *pixel, hd);
+        }
+        assert_eq!(nif.to_bytes(0).unwrap(), golden);
+
+        let nif_read = Nif::from_bytes(golden).unwrap();
+        let frame = &nif_read.get_frames()[0];
+        for (i, pixel) in pixels.iter().enumerate() {
+            assert_eq!(
+                frame.get_pixel(i as u32 % width, i as u32 / width, hd),
+                *pixel
+            );
+        }
+        let iterated: Vec<Pixel> = PixelIterator::new(frame, &nif_read.header).collect();
+        assert_eq!(iterated, pixels);
+    }
+    #[test]
+    fn test_golden_rgba8888() {
+        let golden = include_bytes!("../golden/rgba8888.nif");
+        //channels are stored R, G, B, A
+        assert_eq!(&golden[32..36], &[0x11, 0x22, 0x33, 0x44]);
+        let pixels = [
+            Pixel::RGBA8888(Pixel32U::from_rgba(0x11, 0x22, 0x33, 0x44)),
+            Pixel::RGBA8888(Pixel32U::from_rgba(0xFF, 0, 0, 0xFF)),
+            Pixel::RGBA8888(Pixel32U::from_rgba(0, 0xFF, 0, 0x80)),
+            Pixel::RGBA8888(Pixel32U::from_rgba(0, 0, 0xFF, 0)),
+        ];
+        check_golden(golden, 2, 2, 30.0, &pixels);
+        match pixels[2] {
+            Pixel::RGBA8888(p) => assert_eq!((p.r(), p.g(), p.b(), p.a()), (0, 0xFF, 0, 0x80)),
+            _ => unreachable!(),
+        }
+    }
+    #[test]
+    fn test_golden_rgb888() {
+        let pixels = [
+            Pixel::RGB888(Pixel32U::from_rgba(0x12, 0x34, 0x56, 0)),
+            Pixel::RGB888(Pixel32U::from_rgba(0xAB, 0xCD, 0xEF, 0)),
+        ];
+        check_golden(include_bytes!("../golden/rgb888.nif"), 2, 1, 0.0, &pixels);
+    }
+    #[test]
+    fn test_golden_rgba4444() {
+        let pixels = [
+            Pixel::RGBA4444(Pixel16U::from_rgba(0x1, 0x2, 0x3, 0x4)),
+            Pixel::RGBA4444(Pixel16U::from_rgba(0xF, 0x0, 0xA, 0x5)),
+        ];
+        check_golden(include_bytes!("../golden/rgba4444.nif"), 2, 1, 0.0, &pixels);
+        match pixels[1] {
+            Pixel::RGBA4444(p) => assert_eq!((p.r(), p.g(), p.b(), p.a()), (0xF, 0, 0xA, 0x5)),
+            _ => unreachable!(),
+        }
+    }
+    #[test]
+    fn test_golden_rgb444() {
+        let pixels = [
+            Pixel::RGB444(Pixel16U::new(0x1, 0x2, 0x3)),
+            Pixel::RGB444(Pixel16U::new(0xA, 0xB, 0xC)),
+        ];
+        check_golden(include_bytes!("../golden/rgb444.nif"), 2, 1, 0.0, &pixels);
+    }
+    //a small video whose frames all differ
+    fn test_video(frames: u32) -> Nif {
+        let mut nif = Nif::new(Header {
+            width: 8,
+            height: 6,
+            pixel_format: Pixel::RGBA8888(0.into()),
+            frame_count: 0,
+            rate: Rational::new(60, 1),
+        });
+        for f in 0..frames {
+            nif.new_empty_frame();
+            let frame = nif.get_frame(f).unwrap();
+            for (i, byte) in frame.data.iter_mut().enumerate() {
+                *byte = (i as u32 ^ f.wrapping_mul(31)) as u8;
+            }
+        }
+        nif
+    }
+    #[test]
+    fn test_stream_frames() {
+        let nif = test_video(5);
+        for features in [0, FEATURE_FLAGS_COMPRESSION] {
+            let bytes = nif.to_bytes(features).unwrap();
+            let mut reader = NifReader::new(Trickle(&bytes)).unwrap();
+            assert_eq!(reader.features(), features);
+            assert_eq!(reader.header().frame_count, 5);
+            let first = reader.next_frame().unwrap().unwrap();
+            assert_eq!(&first, &nif.get_frames()[0]);
+            assert_eq!(reader.frame_index(), 1);
+            let rest: Vec<_> = reader.by_ref().map(|frame| frame.unwrap()).collect();
+            assert_eq!(rest, &nif.get_frames()[1..]);
+            assert!(reader.next_frame().unwrap().is_none());
+        }
+    }
+    #[test]
+    fn test_stream_stops_after_error() {
+        let nif = test_video(3);
+        let bytes = nif.to_bytes(0).unwrap();
+        let frame_size = nif.header.frame_size().unwrap();
+        let reader = NifReader::new(&bytes[..bytes.len() - frame_size / 2]).unwrap();
+        let results: Vec<_> = reader.collect();
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok() && results[1].is_ok());
+        assert!(matches!(results[2], Err(Error::Truncated { .. })));
+    }
+    #[test]
+    fn test_writer_patches_frame_count() {
+        let nif = test_video(3);
+        for features in [0, FEATURE_FLAGS_COMPRESSION] {
+            let mut writer =
+                NifWriter::new(std::io::Cursor::new(Vec::new()), nif.header, features).unwrap();
+            for frame in nif.get_frames() {
+                writer.push_frame(frame).unwrap();
+            }
+            assert_eq!(writer.frames_written(), 3);
+            let bytes = writer.finish().unwrap().into_inner();
+            assert_eq!(&bytes[36..40], &3_u32.to_be_bytes());
+            assert_eq!(bytes, nif.to_bytes(features).unwrap());
+        }
+    }
+    #[test]
+    fn test_writer_unknown_frame_count() {
+        let nif = test_video(4);
+        for features in [0, FEATURE_FLAGS_COMPRESSION] {
+            //a Vec cannot seek, so the count stays unknown
+            let mut writer = NifWriter::new_streaming(Vec::new(), nif.header, features).unwrap();
+            for frame in nif.get_frames() {
+                writer.push_frame(frame).unwrap();
+            }
+            let bytes = writer.finish().unwrap();
+            assert_eq!(&bytes[36..40], &FRAME_COUNT_UNKNOWN.to_be_bytes());
+
+            let reader = NifReader::new(&bytes[..]).unwrap();
+            assert_eq!(reader.frame_count(), None);
+            let frames: Vec<_> = reader.map(|frame| frame.unwrap()).collect();
+            assert_eq!(&frames, nif.get_frames());
+
+            let nif_read = Nif::from_bytes(&bytes).unwrap();
+            assert_eq!(nif_read.header.frame_count, 4);
+            assert_eq!(nif_read.get_frames(), nif.get_frames());
+        }
+        //a partial last frame is still an error
+        let mut writer = NifWriter::new_streaming(Vec::new(), nif.header, 0).unwrap();
+        writer.push_frame(&nif.get_frames()[0]).unwrap();
+        let bytes = writer.finish().unwrap();
+        let result = Nif::from_bytes(&bytes[..bytes.len() - 1]);
+        assert!(matches!(result, Err(Error::Truncated { .. })));
+    }
+    #[test]
+    fn test_writer_fixed_frame_count() {
+        let nif = test_video(2);
+        let mut writer = NifWriter::with_frame_count(Vec::new(), nif.header, 0).unwrap();
+        writer.push_frame(&nif.get_frames()[0]).unwrap();
+        let err = writer.finish().unwrap_err();
+        assert!(matches!(
+            err,
+            Error::FrameCountMismatch {
+                expected: 2,
+                found: 1,
+                ..
+            }
+        ));
+
+        let mut writer = NifWriter::with_frame_count(Vec::new(), nif.header, 0).unwrap();
+        for frame in nif.get_frames() {
+            writer.push_frame(frame).unwrap();
+        }
+        assert!(writer.push_frame(&nif.get_frames()[0]).is_err());
+        //a writer that cannot seek leaves the length of the DATA chunk unknown
+        let mut expected = nif.to_bytes(0).unwrap();
+        expected[52..60].copy_from_slice(&[0xFF; 8]);
+        assert_eq!(writer.finish().unwrap(), expected);
+    }
+    #[test]
+    fn test_random_access() {
+        use std::io::{Cursor, Seek, SeekFrom};
+        let nif = test_video(6);
+        //the stream does not have to start at the beginning of the reader
+        let mut bytes = vec![0xAA; 5];
+        bytes.extend(nif.to_bytes(0).unwrap());
+        let mut cursor = Cursor::new(bytes);
+        cursor.seek(SeekFrom::Start(5)).unwrap();
+
+        let mut reader = NifReader::new(cursor).unwrap();
+        assert_eq!(&reader.read_frame_at(4).unwrap(), &nif.get_frames()[4]);
+        assert_eq!(&reader.next_frame().unwrap().unwrap(), &nif.get_frames()[5]);
+        assert!(reader.next_frame().unwrap().is_none());
+        assert_eq!(&reader.read_frame_at(1).unwrap(), &nif.get_frames()[1]);
+        reader.seek_to_frame(0).unwrap();
+        let frames: Vec<_> = reader.by_ref().map(|frame| frame.unwrap()).collect();
+        assert_eq!(&frames, nif.get_frames());
+        assert!(matches!(
+            reader.read_frame_at(6),
+            Err(Error::FrameOutOfRange {
+                index: 6,
+                count: 6,
+                ..
+            })
+        ));
+
+        //compressed frames have no fixed position
+        let bytes = nif.to_bytes(FEATURE_FLAGS_COMPRESSION).unwrap();
+        let mut reader = NifReader::new(Cursor::new(bytes)).unwrap();
+        assert!(matches!(
+            reader.seek_to_frame(1),
+            Err(Error::Unsupported { .. })
+        ));
+
+        //frames whose offset does not fit in a stream
+        let mut huge = Nif::new(Header {
+            width: 1 << 31,
+            height: 1 << 31,
+            pixel_format: Pixel::RGBA4444(0.into()),
+            frame_count: 0,
+            rate: Rational::new(0, 1),
+        });
+        huge.version = VERSION_0_1;
+        let mut bytes = huge.to_bytes(0).unwrap();
+        bytes[24..28].copy_from_slice(&3_u32.to_be_bytes());
+        let mut reader = NifReader::new(Cursor::new(bytes)).unwrap();
+        assert!(matches!(
+            reader.read_frame_at(2),
+            Err(Error::FrameOutOfRange { index: 2, .. })
+        ));
+    }
+    #[test]
+    fn test_random_access_unknown_frame_count() {
+        let nif = test_video(3);
+        let mut writer = NifWriter::new_streaming(Vec::new(), nif.header, 0).unwrap();
+        for frame in nif.get_frames() {
+            writer.push_frame(frame).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+        let mut reader = NifReader::new(std::io::Cursor::new(bytes)).unwrap();
+        assert_eq!(&reader.read_frame_at(2).unwrap(), &nif.get_frames()[2]);
+        assert!(matches!(
+            reader.read_frame_at(3),
+            Err(Error::FrameOutOfRange { index: 3, .. })
+        ));
+    }
+    #[test]
+    fn test_frame_index() {
+        use std::io::Cursor;
+        let nif = test_video(5);
+        for features in [
+            FEATURE_FLAGS_FRAME_INDEX,
+            FEATURE_FLAGS_FRAME_INDEX | FEATURE_FLAGS_COMPRESSION,
+        ] {
+            let bytes = nif.to_bytes(features).unwrap();
+            let read = Nif::from_bytes(&bytes).unwrap();
+            assert_eq!(read.features, features);
+            assert_eq!(read.get_frames(), nif.get_frames());
+
+            let mut reader = NifReader::new(Cursor::new(&bytes)).unwrap();
+            assert_eq!(&reader.read_frame_at(3).unwrap(), &nif.get_frames()[3]);
+            assert_eq!(&reader.read_frame_at(0).unwrap(), &nif.get_frames()[0]);
+            assert_eq!(&reader.next_frame().unwrap().unwrap(), &nif.get_frames()[1]);
+            assert!(matches!(
+                reader.read_frame_at(5),
+                Err(Error::FrameOutOfRange { index: 5, .. })
+            ));
+
+            //the frames before a cut still decode
+            let cut = &bytes[..bytes.len() * 3 / 4];
+            let mut reader = NifReader::new(cut).unwrap();
+            let mut recovered = 0;
+            let result = loop {
+                match reader.next_frame() {
+                    Ok(Some(frame)) => {
+                        assert_eq!(&frame, &nif.get_frames()[recovered]);
+                        recovered += 1;
+                    }
+                    result => break result,
+                }
+            };
+            assert!(recovered >= 2);
+            assert!(matches!(result, Err(Error::Truncated { .. })));
+        }
+
+        //streams of unknown length get an index too
+        let features = FEATURE_FLAGS_FRAME_INDEX | FEATURE_FLAGS_COMPRESSION;
+        let mut writer = NifWriter::new_streaming(Vec::new(), nif.header, features).unwrap();
+        for frame in nif.get_frames() {
+            writer.push_frame(frame).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+        let mut reader = NifReader::new(Cursor::new(&bytes)).unwrap();
+        assert_eq!(reader.frame_count(), None);
+        assert_eq!(&reader.read_frame_at(4).unwrap(), &nif.get_frames()[4]);
+        assert!(reader.next_frame().unwrap().is_none());
+        let frames: Vec<_> = NifReader::new(&bytes[..])
+            .unwrap()
+            .map(|frame| frame.unwrap())
+            .collect();
+        assert_eq!(&frames, nif.get_frames());
+    }
+    #[test]
+    fn test_frame_index_rejects_corrupt_records() {
+        let nif = test_video(2);
+        let bytes = nif
+            .to_bytes(FEATURE_FLAGS_FRAME_INDEX | FEATURE_FLAGS_COMPRESSION)
+            .unwrap();
+        //unknown record type
+        let mut corrupt = bytes.clone();
+        corrupt[60] = 0x7F;
+        assert!(matches!(
+            Nif::from_bytes(&corrupt),
+            Err(Error::Corrupt { offset: 60, .. })
+        ));
+        //broken footer tag
+        let mut corrupt = bytes.clone();
+        *corrupt.last_mut().unwrap() = 0;
+        assert!(matches!(
+            Nif::from_bytes(&corrupt),
+            Err(Error::Corrupt { .. })
+        ));
+        //a footer or index that points anywhere is caught on random access
+        let footer = bytes.len() - 16;
+        let index = footer - 2 * 13;
+        for (at, value) in [
+            (footer + 8, &[0xFF; 4][..]),
+            (footer, &[0xFF; 8][..]),
+            (footer, &[0; 8][..]),
+            (index, &[0xFF; 8][..]),
+        ] {
+            let mut corrupt = bytes.clone();
+            corrupt[at..at + value.len()].copy_from_slice(value);
+            let mut reader = NifReader::new(std::io::Cursor::new(&corrupt)).unwrap();
+            assert!(matches!(
+                reader.read_frame_at(1),
+                Err(Error::Corrupt { .. })
+            ));
+        }
+        //a byte after the DATA chunk starts a chunk that is cut off
+        let mut corrupt = bytes.clone();
+        corrupt.push(0);
+        assert!(matches!(
+            Nif::from_bytes(&corrupt),
+            Err(Error::Truncated { .. })
+        ));
+        let mut nif = nif;
+        nif.version = VERSION_0_1;
+        let mut corrupt = nif
+            .to_bytes(FEATURE_FLAGS_FRAME_INDEX | FEATURE_FLAGS_COMPRESSION)
+            .unwrap();
+        corrupt.push(0);
+        assert!(matches!(
+            Nif::from_bytes(&corrupt),
+            Err(Error::TrailingData { .. })
+        ));
+    }
+    #[test]
+    fn test_codecs() {
+        let nif = test_video(3);
+        let mut codecs = vec![0, FEATURE_FLAGS_CODEC_DEFLATE, FEATURE_FLAGS_CODEC_QOI];
+        if cfg!(feature = "zstd") {
+            codecs.push(FEATURE_FLAGS_CODEC_ZSTD);
+        }
+        if cfg!(feature = "lz4") {
+            codecs.push(FEATURE_FLAGS_CODEC_LZ4);
+        }
+        for codec in codecs {
+            let features = FEATURE_FLAGS_COMPRESSION | FEATURE_FLAGS_FRAME_INDEX | codec;
+            for level in [None, Some(1), Some(9)] {
+                let options = WriteOptions {
+                    level,
+                    ..WriteOptions::new(features)
+                };
+                let bytes = nif.to_bytes(options).unwrap();
+                let read = Nif::from_bytes(&bytes).unwrap();
+                assert_eq!(read.features, features);
+                assert_eq!(read.get_frames(), nif.get_frames());
+            }
+        }
+
+        //a flat frame compresses better at a higher level
+        let mut flat = Nif::new(Header {
+            frame_count: 0,
+            ..nif.header
+        });
+        flat.new_empty_frame();
+        let features = FEATURE_FLAGS_COMPRESSION;
+        let fast = flat.to_bytes(WriteOptions::new(features).level(0)).unwrap();
+        let best = flat.to_bytes(WriteOptions::new(features).level(9)).unwrap();
+        assert!(best.len() < fast.len());
+        assert_eq!(
+            Nif::from_bytes(&fast).unwrap().get_frames(),
+            flat.get_frames()
+        );
+    }
+    #[test]
+    fn test_codecs_reject_huge_frames() {
+        let nif = test_video(1);
+        let mut codecs = vec![0, FEATURE_FLAGS_CODEC_DEFLATE, FEATURE_FLAGS_CODEC_QOI];
+        if cfg!(feature = "zstd") {
+            codecs.push(FEATURE_FLAGS_CODEC_ZSTD);
+        }
+        if cfg!(feature = "lz4") {
+            codecs.push(FEATURE_FLAGS_CODEC_LZ4);
+        }
+        for codec in codecs {
+            let features = FEATURE_FLAGS_COMPRESSION | FEATURE_FLAGS_FRAME_INDEX | codec;
+            let mut bytes = nif.to_bytes(features).unwrap();
+            //a tiny file claiming frames of 2 TiB must fail without trying
+            //to allocate them
+            bytes[24..28].copy_from_slice(&(1_u32 << 20).to_be_bytes());
+            bytes[28..32].copy_from_slice(&(1_u32 << 19).to_be_bytes());
+            assert!(matches!(
+                Nif::from_bytes(&bytes),
+                Err(Error::Corrupt { .. })
+            ));
+            let mut reader = NifReader::new(&bytes[..]).unwrap();
+            assert!(matches!(reader.next_frame(), Err(Error::Corrupt { .. })));
+        }
+    }
+    #[test]
+    fn test_codec_flags_rejected() {
+        let nif = test_video(1);
+        //only gzip works as a single stream
+        assert!(matches!(
+            nif.to_bytes(FEATURE_FLAGS_COMPRESSION | FEATURE_FLAGS_CODEC_DEFLATE),
+            Err(Error::Unsupported { offset: 8, .. })
+        ));
+        //a codec without compression
+        assert!(matches!(
+            nif.to_bytes(FEATURE_FLAGS_FRAME_INDEX | FEATURE_FLAGS_CODEC_DEFLATE),
+            Err(Error::UnknownFeatureFlags { offset: 8, .. })
+        ));
+        //codec ids that are not defined
+        let mut bytes = nif
+            .to_bytes(FEATURE_FLAGS_COMPRESSION | FEATURE_FLAGS_FRAME_INDEX)
+            .unwrap();
+        bytes[10] = (FEATURE_FLAGS_CODEC_MASK >> 8) as u8;
+        assert!(matches!(
+            Nif::from_bytes(&bytes),
+            Err(Error::UnknownFeatureFlags {
+                offset: 8,
+                flags: FEATURE_FLAGS_CODEC_MASK
+            })
+        ));
+    }
+    #[test]
+    fn test_row_filters() {
+        //a smooth gradient, where prediction pays off
+        let mut nif = Nif::new(Header {
+            width: 64,
+            height: 48,
+            pixel_format: Pixel::RGBA8888(0.into()),
+            frame_count: 0,
+            rate: Rational::new(0, 1),
+        });
+        nif.new_empty_frame();
+        let hd = nif.header;
+        for y in 0..hd.height {
+            for x in 0..hd.width {
+                let pixel = Pixel32U::from_rgba((x * 4) as u8, (y * 5) as u8, (x + y) as u8, 0xFF);
+                nif.get_frame(0)
+                    .unwrap()
+                    .set_pixel(x, y, Pixel::RGBA8888(pixel), hd);
+            }
+        }
+        let plain = nif.to_bytes(FEATURE_FLAGS_COMPRESSION).unwrap();
+        let filtered = nif
+            .to_bytes(FEATURE_FLAGS_COMPRESSION | FEATURE_FLAGS_FILTER)
+            .unwrap();
+        assert!(filtered.len() * 2 < plain.len());
+        assert_eq!(
+            Nif::from_bytes(&filtered).unwrap().get_frames(),
+            nif.get_frames()
+        );
+
+        let mut rng = rand::thread_rng();
+        for pixel_format in [Pixel::RGBA8888(0.into()), Pixel::RGBA4444(0.into())] {
+            let mut nif = Nif::new(Header {
+                width: 7,
+                height: 5,
+                pixel_format,
+                frame_count: 0,
+                rate: Rational::new(0, 1),
+            });
+            for f in 0..3 {
+                nif.new_empty_frame();
+                rng.fill(&mut nif.get_frame(f).unwrap().data[..]);
+            }
+            for features in [
+                FEATURE_FLAGS_COMPRESSION | FEATURE_FLAGS_FILTER,
+                FEATURE_FLAGS_COMPRESSION | FEATURE_FLAGS_FILTER | FEATURE_FLAGS_FRAME_INDEX,
+            ] {
+                let bytes = nif.to_bytes(features).unwrap();
+                let read = Nif::from_bytes(&bytes).unwrap();
+                assert_eq!(read.features, features);
+                assert_eq!(read.get_frames(), nif.get_frames());
+            }
+        }
+
+        assert!(matches!(
+            nif.to_bytes(FEATURE_FLAGS_FILTER),
+            Err(Error::Unsupported { offset: 8, .. })
+        ));
+    }
+    #[test]
+    fn test_delta_frames() {
+        use std::io::Cursor;
+        //a mostly static screen with a small moving square
+        let mut nif = Nif::new(Header {
+            width: 32,
+            height: 32,
+            pixel_format: Pixel::RGBA8888(0.into()),
+            frame_count: 0,
+            rate: Rational::new(30, 1),
+        });
+        let hd = nif.header;
+        let mut rng = rand::thread_rng();
+        let mut background = vec![0; hd.frame_size().unwrap()];
+        rng.fill(&mut background[..]);
+        for f in 0..7 {
+            nif.new_empty_frame();
+            let frame = nif.get_frame(f).unwrap();
+            frame.data.copy_from_slice(&background);
+            let white = Pixel::RGBA8888(Pixel32U::from_rgba(0xFF, 0xFF, 0xFF, 0xFF));
+            for y in 10..14 {
+                for x in f * 2..f * 2 + 4 {
+                    frame.set_pixel(x, y, white, hd);
+                }
+            }
+        }
+
+        let features = FEATURE_FLAGS_COMPRESSION | FEATURE_FLAGS_FRAME_INDEX;
+        let full = nif.to_bytes(features).unwrap();
+        for interval in [0, 1, 3] {
+            let options =
+                WriteOptions::new(features | FEATURE_FLAGS_DELTA).keyframe_interval(interval);
+            let bytes = nif.to_bytes(options).unwrap();
+            if interval != 1 {
+                //three whole frames out of eight
+                assert!(bytes.len() * 3 < full.len() * 2);
+            }
+            let read = Nif::from_bytes(&bytes).unwrap();
+            assert_eq!(read.get_frames(), nif.get_frames());
+
+            let mut reader = NifReader::new(Cursor::new(&bytes)).unwrap();
+            for index in [5, 2, 6, 0, 4] {
+                assert_eq!(
+                    &reader.read_frame_at(index).unwrap(),
+                    &nif.get_frames()[index as usize]
+                );
+            }
+            assert_eq!(&reader.next_frame().unwrap().unwrap(), &nif.get_frames()[5]);
+        }
+
+        //deltas combine with the row filters and frames without compression
+        for features in [
+            FEATURE_FLAGS_FRAME_INDEX | FEATURE_FLAGS_DELTA,
+            features | FEATURE_FLAGS_DELTA | FEATURE_FLAGS_FILTER,
+        ] {
+            let bytes = nif.to_bytes(features).unwrap();
+            assert_eq!(
+                Nif::from_bytes(&bytes).unwrap().get_frames(),
+                nif.get_frames()
+            );
+        }
+        assert!(matches!(
+            nif.to_bytes(FEATURE_FLAGS_COMPRESSION | FEATURE_FLAGS_DELTA),
+            Err(Error::Unsupported { offset: 8, .. })
+        ));
+    }
+    #[test]
+    fn test_dirty_rects() {
+        use std::io::Cursor;
+        //a cursor moving over a static window, then a full repaint
+        let mut nif = Nif::new(Header {
+            width: 70,
+            height: 50,
+            pixel_format: Pixel::RGBA4444(0.into()),
+            frame_count: 0,
+            rate: Rational::new(30, 1),
+        });
+        let hd = nif.header;
+        let mut rng = rand::thread_rng();
+        let mut background = vec![0; hd.frame_size().unwrap()];
+        rng.fill(&mut background[..]);
+        for f in 0..8 {
+            nif.new_empty_frame();
+            let frame = nif.get_frame(f).unwrap();
+            if f == 6 {
+                rng.fill(&mut frame.data[..]);
+                continue;
+            }
+            frame.data.copy_from_slice(&background);
+            let cursor = Pixel::RGBA4444(Pixel16U::from_rgba(0xF, 0, 0, 0xF));
+            for (x, y) in [(f * 9, f * 5), (69, 49 - f), (f * 9 + 1, 20)] {
+                frame.set_pixel(x, y, cursor, hd);
+            }
+        }
+
+        let features = FEATURE_FLAGS_COMPRESSION | FEATURE_FLAGS_FRAME_INDEX;
+        let full = nif.to_bytes(features).unwrap();
+        for features in [
+            features | FEATURE_FLAGS_DIRTY_RECTS,
+            features | FEATURE_FLAGS_DIRTY_RECTS | FEATURE_FLAGS_DELTA | FEATURE_FLAGS_FILTER,
+            FEATURE_FLAGS_FRAME_INDEX | FEATURE_FLAGS_DIRTY_RECTS,
+        ] {
+            let bytes = nif
+                .to_bytes(WriteOptions::new(features).keyframe_interval(0))
+                .unwrap();
+            //three whole frames out of eight
+            assert!(bytes.len() * 3 < full.len() * 2);
+            let read = Nif::from_bytes(&bytes).unwrap();
+            assert_eq!(read.get_frames(), nif.get_frames());
+
+            let mut reader = NifReader::new(Cursor::new(&bytes)).unwrap();
+            for index in [7, 3, 0, 5] {
+                assert_eq!(
+                    &reader.read_frame_at(index).unwrap(),
+                    &nif.get_frames()[index as usize]
+                );
+            }
+        }
+        assert!(matches!(
+            nif.to_bytes(FEATURE_FLAGS_DIRTY_RECTS),
+            Err(Error::Unsupported { offset: 8, .. })
+        ));
+    }
+    #[test]
+    fn test_qoi_codec() {
+        use crate::nif::{Codec, Qoi};
+        let features =
+            FEATURE_FLAGS_COMPRESSION | FEATURE_FLAGS_FRAME_INDEX | FEATURE_FLAGS_CODEC_QOI;
+        let mut rng = rand::thread_rng();
+        for pixel_format in [
+            Pixel::RGBA8888(0.into()),
+            Pixel::RGB888(0.into()),
+            Pixel::RGBA4444(0.into()),
+            Pixel::RGB444(0.into()),
+        ] {
+            let mut nif = Nif::new(Header {
+                width: 40,
+                height: 30,
+                pixel_format,
+                frame_count: 0,
+                rate: Rational::new(0, 1),
+            });
+            let hd = nif.header;
+            //a gradient with a flat band and a noisy band
+            nif.new_empty_frame();
+            for y in 0..hd.height {
+                for x in 0..hd.width {
+                    let (r, g, b) = match y {
+                        0..=9 => (x as u8, y as u8, (x + y) as u8),
+                        10..=19 => (7, 7, 7),
+                        _ => (rng.gen(), rng.gen(), rng.gen()),
+                    };
+                    let pixel = match pixel_format {
+                        Pixel::RGBA8888(_) | Pixel::RGB888(_) => {
+                            Pixel::RGBA8888(Pixel32U::from_rgba(r, g, b, 0xFF))
+                        }
+                        _ => Pixel::RGBA4444(Pixel16U::from_rgba(r & 0xF, g & 0xF, b & 0xF, 0xF)),
+                    };
+                    nif.get_frame(0).unwrap().set_pixel(x, y, pixel, hd);
+                }
+            }
+            nif.new_empty_frame();
+            rng.fill(&mut nif.get_frame(1).unwrap().data[..]);
+
+            for features in [
+                features,
+                features | FEATURE_FLAGS_DIRTY_RECTS | FEATURE_FLAGS_DELTA,
+            ] {
+                let bytes = nif.to_bytes(features).unwrap();
+                assert_eq!(
+                    Nif::from_bytes(&bytes).unwrap().get_frames(),
+                    nif.get_frames()
+                );
+            }
+        }
+
+        //bytes after the last whole pixel are kept
+        let data: Vec<u8> = (0..103).map(|i| (i / 10) as u8).collect();
+        let compressed = Qoi.compress(&data, None).unwrap();
+        assert!(compressed.len() < data.len());
+        assert_eq!(Qoi.decompress(&compressed, data.len()).unwrap(), data);
+        assert!(Qoi.decompress(&compressed, data.len() - 1).is_err());
+        assert!(Qoi
+            .decompress(&compressed[..compressed.len() - 4], data.len())
+            .is_err());
+    }
+    #[test]
+    fn test_run_length() {
+        let features = FEATURE_FLAGS_FRAME_INDEX | FEATURE_FLAGS_RLE;
+        let mut rng = rand::thread_rng();
+        for pixel_format in [
+            Pixel::RGBA8888(0.into()),
+            Pixel::RGB888(0.into()),
+            Pixel::RGBA4444(0.into()),
+            Pixel::RGB444(0.into()),
+        ] {
+            let mut nif = Nif::new(Header {
+                width: 300,
+                height: 20,
+                pixel_format,
+                frame_count: 0,
+                rate: Rational::new(0, 1),
+            });
+            let hd = nif.header;
+            //a flat sprite on a flat background, then a noisy frame
+            nif.new_empty_frame();
+            for y in 0..hd.height {
+                for x in 0..hd.width {
+                    let (r, g, b) = match (x, y) {
+                        (100..=199, 5..=14) => (9, 3, 1),
+                        _ => (1, 2, 3),
+                    };
+                    let pixel = match pixel_format {
+                        Pixel::RGBA8888(_) | Pixel::RGB888(_) => {
+                            Pixel::RGBA8888(Pixel32U::from_rgba(r, g, b, 0xFF))
+                        }
+                        _ => Pixel::RGBA4444(Pixel16U::from_rgba(r, g, b, 0xF)),
+                    };
+                    nif.get_frame(0).unwrap().set_pixel(x, y, pixel, hd);
+                }
+            }
+            let flat = nif.to_bytes(features).unwrap();
+            assert!(flat.len() * 20 < nif.get_frame(0).unwrap().data.len());
+            nif.new_empty_frame();
+            rng.fill(&mut nif.get_frame(1).unwrap().data[..]);
+
+            for features in [
+                features,
+                features | FEATURE_FLAGS_DIRTY_RECTS | FEATURE_FLAGS_DELTA,
+            ] {
+                let bytes = nif.to_bytes(features).unwrap();
+                assert_eq!(
+                    Nif::from_bytes(&bytes).unwrap().get_frames(),
+                    nif.get_frames()
+                );
+            }
+        }
+
+        let nif = test_video(1);
+        assert!(matches!(
+            nif.to_bytes(features | FEATURE_FLAGS_COMPRESSION),
+            Err(Error::Unsupported { offset: 8, .. })
+        ));
+        assert!(matches!(
+            nif.to_bytes(FEATURE_FLAGS_RLE),
+            Err(Error::Unsupported { offset: 8, .. })
+        ));
+
+        //a run longer than the row
+        let mut bytes = nif.to_bytes(features).unwrap();
+        bytes[65] = 0xFF;
+        assert!(matches!(
+            Nif::from_bytes(&bytes),
+            Err(Error::Corrupt { offset: 60, .. })
+        ));
+
+        //a tiny file claiming frames of 4 TiB fails without trying to
+        //allocate them
+        let mut tiny = Nif::new(Header {
+            width: 1,
+            height: 1,
+            frame_count: 0,
+            ..nif.header
+        });
+        tiny.version = VERSION_0_1;
+        tiny.new_empty_frame();
+        let mut bytes = tiny.to_bytes(features).unwrap();
+        bytes[12..16].copy_from_slice(&(1_u32 << 20).to_be_bytes());
+        bytes[16..20].copy_from_slice(&(1_u32 << 20).to_be_bytes());
+        assert!(matches!(
+            Nif::from_bytes(&bytes),
+            Err(Error::Corrupt { .. })
+        ));
+    }
+    #[test]
+    fn test_push_frames() {
+        let nif = test_video(40);
+        let records = FEATURE_FLAGS_COMPRESSION | FEATURE_FLAGS_FRAME_INDEX;
+        for features in [
+            0,
+            FEATURE_FLAGS_COMPRESSION,
+            records | FEATURE_FLAGS_FILTER,
+            records | FEATURE_FLAGS_CODEC_QOI | FEATURE_FLAGS_DELTA,
+            FEATURE_FLAGS_FRAME_INDEX | FEATURE_FLAGS_RLE | FEATURE_FLAGS_DIRTY_RECTS,
+        ] {
+            let options = WriteOptions::new(features).keyframe_interval(7);
+            //frames pushed one at a time
+            let mut writer =
+                NifWriter::new(std::io::Cursor::new(Vec::new()), nif.header, options).unwrap();
+            for frame in nif.get_frames() {
+                writer.push_frame(frame).unwrap();
+            }
+            let expected = writer.finish().unwrap().into_inner();
+            assert_eq!(nif.to_bytes(options).unwrap(), expected);
+            #[cfg(feature = "parallel")]
+            for threads in [1, 3, 8] {
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .unwrap();
+                pool.install(|| {
+                    assert_eq!(nif.to_bytes(options).unwrap(), expected);
+                    assert_eq!(
+                        Nif::from_bytes(&expected).unwrap().get_frames(),
+                        nif.get_frames()
+                    );
+                });
+            }
+        }
+
+        //the frames before a bad one are written
+        let mut bad = test_video(40);
+        bad.get_frame(25).unwrap().data.pop();
+        let frames = bad.get_frames();
+        let mut writer = NifWriter::new_streaming(Vec::new(), nif.header, records).unwrap();
+        assert!(matches!(
+            writer.push_frames(frames),
+            Err(Error::FrameSizeMismatch { index: 25, .. })
+        ));
+        assert_eq!(writer.frames_written(), 25);
+        let bytes = writer.finish().unwrap();
+        assert_eq!(
+            Nif::from_bytes(&bytes).unwrap().get_frames()[..],
+            nif.get_frames()[..25]
+        );
+
+        //corrupt records are reported in order
+        let mut bytes = nif.to_bytes(FEATURE_FLAGS_FRAME_INDEX).unwrap();
+        //the type of the 12th record, each is 5 + 192 bytes
+        let corrupt = 60 + 11 * 197;
+        bytes[corrupt] = 9;
+        let mut reader = NifReader::new(&bytes[..]).unwrap();
+        assert!(matches!(
+            reader.read_remaining(),
+            Err(Error::Corrupt { offset, .. }) if offset == corrupt as u64
+        ));
+        assert!(reader.next_frame().unwrap().is_none());
+    }
+    #[test]
+    fn test_tiles() {
+        use crate::nif::{Tile, TILE_SIZE};
+        use std::io::Cursor;
+        let mut nif = Nif::new(Header {
+            width: 600,
+            height: 300,
+            pixel_format: Pixel::RGBA8888(0.into()),
+            frame_count: 0,
+            rate: Rational::new(0, 1),
+        });
+        let hd = nif.header;
+        for f in 0..2 {
+            nif.new_empty_frame();
+            for y in 0..hd.height {
+                for x in 0..hd.width {
+                    let (r, g, b) = ((x / 3) as u8, (y / 2) as u8, if x > 400 { f } else { 9 });
+                    let pixel = Pixel::RGBA8888(Pixel32U::from_rgba(r, g, b, 0xFF));
+                    nif.get_frame(f as u32).unwrap().set_pixel(x, y, pixel, hd);
+                }
+            }
+        }
+        let tiled = FEATURE_FLAGS_FRAME_INDEX | FEATURE_FLAGS_TILES;
+        let records = tiled | FEATURE_FLAGS_COMPRESSION;
+        for features in [
+            tiled,
+            tiled | FEATURE_FLAGS_RLE,
+            records,
+            records | FEATURE_FLAGS_FILTER,
+            records | FEATURE_FLAGS_CODEC_QOI,
+        ] {
+            let bytes = nif.to_bytes(features).unwrap();
+            assert_eq!(
+                Nif::from_bytes(&bytes).unwrap().get_frames(),
+                nif.get_frames()
+            );
+
+            let mut reader = NifReader::new(Cursor::new(&bytes)).unwrap();
+            for (tx, ty) in [(0, 0), (2, 1), (1, 1)] {
+                let tile = reader.read_tile(1, tx, ty).unwrap();
+                let (x, y) = (tx * TILE_SIZE, ty * TILE_SIZE);
+                let (width, height) = (
+                    (hd.width - x).min(TILE_SIZE),
+                    (hd.height - y).min(TILE_SIZE),
+                );
+                let mut data = Vec::new();
+                for row in y..y + height {
+                    let start = (row * hd.width + x) as usize * 4;
+                    data.extend_from_slice(
+                        &nif.get_frames()[1].data[start..start + width as usize * 4],
+                    );
+                }
+                assert_eq!(
+                    tile,
+                    Tile {
+                        x,
+                        y,
+                        width,
+                        height,
+                        data
+                    }
+                );
+            }
+            //reading tiles does not move the reader
+            assert_eq!(&reader.next_frame().unwrap().unwrap(), &nif.get_frames()[0]);
+            assert!(matches!(
+                reader.read_tile(0, 3, 0),
+                Err(Error::TileOutOfRange {
+                    tx: 3,
+                    tiles_x: 3,
+                    tiles_y: 2,
+                    ..
+                })
+            ));
+            assert!(matches!(
+                reader.read_tile(2, 0, 0),
+                Err(Error::FrameOutOfRange { index: 2, .. })
+            ));
+            assert_eq!(&reader.next_frame().unwrap().unwrap(), &nif.get_frames()[1]);
+        }
+
+        let bytes = nif.to_bytes(FEATURE_FLAGS_FRAME_INDEX).unwrap();
+        let mut reader = NifReader::new(Cursor::new(&bytes)).unwrap();
+        assert!(matches!(
+            reader.read_tile(0, 0, 0),
+            Err(Error::Unsupported { .. })
+        ));
+        for features in [FEATURE_FLAGS_TILES, tiled | FEATURE_FLAGS_DELTA] {
+            assert!(matches!(
+                nif.to_bytes(features),
+                Err(Error::Unsupported { offset: 8, .. })
+            ));
+        }
+    }
+    #[test]
+    fn test_interlace() {
+        use crate::nif::{decode_partial, FEATURE_FLAGS_INTERLACE};
+        let mut nif = Nif::new(Header {
+            width: 37,
+            height: 23,
+            pixel_format: Pixel::RGB888(0.into()),
+            frame_count: 0,
+            rate: Rational::new(0, 1),
+        });
+        let hd = nif.header;
+        for f in 0..2 {
+            nif.new_empty_frame();
+            for y in 0..hd.height {
+                for x in 0..hd.width {
+                    let pixel = Pixel::RGB888(Pixel32U::from_rgba(x as u8 * 7, y as u8, f, 0));
+                    nif.get_frame(f as u32).unwrap().set_pixel(x, y, pixel, hd);
+                }
+            }
+        }
+        let first = &nif.get_frames()[0].data;
+        let records = FEATURE_FLAGS_FRAME_INDEX | FEATURE_FLAGS_INTERLACE;
+        for features in [
+            FEATURE_FLAGS_INTERLACE,
+            FEATURE_FLAGS_INTERLACE | FEATURE_FLAGS_COMPRESSION | FEATURE_FLAGS_FILTER,
+            records,
+            records | FEATURE_FLAGS_RLE,
+            records | FEATURE_FLAGS_COMPRESSION | FEATURE_FLAGS_FILTER,
+            records | FEATURE_FLAGS_COMPRESSION | FEATURE_FLAGS_CODEC_QOI,
+            records | FEATURE_FLAGS_DELTA,
+            records | FEATURE_FLAGS_TILES,
+        ] {
+            let bytes = nif.to_bytes(features).unwrap();
+            assert_eq!(
+                Nif::from_bytes(&bytes).unwrap().get_frames(),
+                nif.get_frames()
+            );
+            let preview = decode_partial(&bytes).unwrap();
+            assert!(preview.complete);
+            assert_eq!(&preview.frame.data, first);
+            //any start of the stream gives a preview
+            for len in [64, 60 + (bytes.len() - 60) / 4] {
+                let preview = decode_partial(&bytes[..len]).unwrap();
+                assert!(!preview.complete);
+                assert_eq!(preview.frame.data.len(), first.len());
+            }
+        }
+
+        //the first pass alone fills the frame with blocks of 8 by 8 pixels
+        let bytes = nif.to_bytes(FEATURE_FLAGS_INTERLACE).unwrap();
+        let pass_len = 5 * 3 * 4;
+        let preview = decode_partial(&bytes[..60 + pass_len]).unwrap();
+        for y in 0..hd.height as usize {
+            for x in 0..hd.width as usize {
+                let block = ((y & !7) * hd.width as usize + (x & !7)) * 4;
+                let pixel = (y * hd.width as usize + x) * 4;
+                assert_eq!(
+                    preview.frame.data[pixel..pixel + 4],
+                    first[block..block + 4]
+                );
+            }
+        }
+        //streams that are not interlaced give the rows at the top
+        let bytes = nif.to_bytes(FEATURE_FLAGS_FRAME_INDEX).unwrap();
+        let row = hd.width as usize * 4;
+        let preview = decode_partial(&bytes[..61 + row * 5 + 10]).unwrap();
+        assert_eq!(preview.frame.data[..row * 5], first[..row * 5]);
+        assert!(preview.frame.data[row * 5..].iter().all(|&b| b == 0));
+
+        assert!(matches!(
+            decode_partial(&bytes[..20]),
+            Err(Error::Truncated { .. })
+        ));
+
+        //a tiny file claiming frames of 2 TiB fails without trying to
+        //allocate them
+        let mut tiny = Nif::new(Header {
+            width: 1,
+            height: 1,
+            pixel_format: Pixel::RGBA8888(0.into()),
+            frame_count: 0,
+            rate: Rational::new(0, 1),
+        });
+        tiny.version = VERSION_0_1;
+        tiny.new_empty_frame();
+        for features in [0, FEATURE_FLAGS_INTERLACE, FEATURE_FLAGS_FRAME_INDEX] {
+            let mut bytes = tiny.to_bytes(features).unwrap();
+            bytes[12..16].copy_from_slice(&(1_u32 << 20).to_be_bytes());
+            bytes[16..20].copy_from_slice(&(1_u32 << 19).to_be_bytes());
+            assert!(matches!(
+                decode_partial(&bytes[..36]),
+                Err(Error::DimensionOverflow { .. })
+            ));
+        }
+    }
+    #[test]
+    fn test_mipmaps() {
+        use crate::nif::MipmapFilter;
+        let mut nif = Nif::new(Header {
+            width: 600,
+            height: 300,
+            pixel_format: Pixel::RGBA8888(0.into()),
+            frame_count: 0,
+            rate: Rational::new(0, 1),
+        });
+        assert_eq!(nif.header.level_count(), 10);
+        assert_eq!(
+            (nif.header.level(1).width, nif.header.level(1).height),
+            (300, 150)
+        );
+        assert_eq!(
+            (nif.header.level(9).width, nif.header.level(9).height),
+            (1, 1)
+        );
+        let hd = nif.header;
+        nif.new_empty_frame();
+        //a checkerboard with a flat square in it
+        for y in 0..hd.height {
+            for x in 0..hd.width {
+                let v = match (x, y) {
+                    (100..=227, 100..=227) => 40,
+                    _ if (x + y) % 2 == 0 => 0xFF,
+                    _ => 0,
+                };
+                let pixel = Pixel::RGBA8888(Pixel32U::from_rgba(v, v, v, 0xFF));
+                nif.get_frame(0).unwrap().set_pixel(x, y, pixel, hd);
+            }
+        }
+        assert!(nif.level(1).is_none());
+        nif.generate_levels(MipmapFilter::Box);
+        let level = nif.level(1).unwrap();
+        assert_eq!(level.data.len(), 300 * 150 * 4);
+        let level_header = hd.level(1);
+        assert_eq!(
+            level.get_pixel(10, 10, level_header),
+            Pixel::RGBA8888(Pixel32U::from_rgba(128, 128, 128, 0xFF))
+        );
+        assert_eq!(
+            level.get_pixel(60, 60, level_header),
+            Pixel::RGBA8888(Pixel32U::from_rgba(40, 40, 40, 0xFF))
+        );
+        assert_eq!(nif.level(9).unwrap().data.len(), 4);
+        assert!(nif.level(10).is_none());
+
+        let mipmaps = FEATURE_FLAGS_FRAME_INDEX | FEATURE_FLAGS_MIPMAPS;
+        for filter in [MipmapFilter::Box, MipmapFilter::Lanczos3] {
+            nif.generate_levels(filter);
+            for features in [
+                mipmaps,
+                mipmaps | FEATURE_FLAGS_COMPRESSION | FEATURE_FLAGS_FILTER,
+                mipmaps | FEATURE_FLAGS_RLE | FEATURE_FLAGS_TILES,
+            ] {
+                let options = WriteOptions::new(features).mipmap_filter(filter);
+                let bytes = nif.to_bytes(options).unwrap();
+                let read = Nif::from_bytes(&bytes).unwrap();
+                assert_eq!(read.get_frames(), nif.get_frames());
+                for n in 0..11 {
+                    assert_eq!(read.level(n), nif.level(n));
+                }
+            }
+        }
+
+        //readers that do not know the flag skip the levels
+        let mut bytes = nif.to_bytes(mipmaps).unwrap();
+        bytes[9] = 0;
+        let read = Nif::from_bytes(&bytes).unwrap();
+        assert_eq!(read.features, FEATURE_FLAGS_FRAME_INDEX);
+        assert_eq!(read.get_frames(), nif.get_frames());
+        assert!(read.level(1).is_none());
+        //unknown ancillary flags are ignored
+        let mut bytes = nif.to_bytes(FEATURE_FLAGS_FRAME_INDEX).unwrap();
+        bytes[8] = 0x80;
+        assert_eq!(
+            Nif::from_bytes(&bytes).unwrap().features,
+            FEATURE_FLAGS_FRAME_INDEX
+        );
+
+        assert!(matches!(
+            nif.to_bytes(FEATURE_FLAGS_MIPMAPS),
+            Err(Error::Unsupported { offset: 8, .. })
+        ));
+        nif.new_empty_frame();
+        assert!(matches!(
+            nif.to_bytes(mipmaps),
+            Err(Error::Unsupported { .. })
+        ));
+    }
+    #[test]
+    fn test_chunks() {
+        use crate::nif::{CURRENT_VERSION, VERSION_0_3};
+        use std::io::Cursor;
+        let nif = test_video(3);
+        let records = FEATURE_FLAGS_FRAME_INDEX | FEATURE_FLAGS_COMPRESSION;
+        let bytes = nif.to_bytes(records).unwrap();
+        assert_eq!(&bytes[4..8], &VERSION_0_3.to_be_bytes());
+        assert_eq!(&bytes[12..16], b"HEAD");
+        assert_eq!(&bytes[16..24], &24_u64.to_be_bytes());
+        assert_eq!(&bytes[48..52], b"DATA");
+        assert_eq!(&bytes[52..60], &(bytes.len() as u64 - 60).to_be_bytes());
+
+        //ancillary chunks are skipped before and after the frames
+        let chunk = |tag: &[u8; 4], data: &[u8]| {
+            [&tag[..], &(data.len() as u64).to_be_bytes(), data].concat()
+        };
+        let with_chunks = [
+            &bytes[..48],
+            &chunk(b"abcd", &[1, 2, 3]),
+            &bytes[48..],
+            &chunk(b"zzzz", &[]),
+        ]
+        .concat();
+        let read = Nif::from_bytes(&with_chunks).unwrap();
+        assert_eq!(read.version, CURRENT_VERSION);
+        assert_eq!(read.get_frames(), nif.get_frames());
+        //the frame index is found at the end of the DATA chunk
+        let mut reader = NifReader::new(Cursor::new(&with_chunks)).unwrap();
+        assert_eq!(&reader.read_frame_at(2).unwrap(), &nif.get_frames()[2]);
+        //other unknown chunks are errors
+        let critical = [&bytes[..48], &chunk(b"ABCD", &[1]), &bytes[48..]].concat();
+        assert!(matches!(
+            Nif::from_bytes(&critical),
+            Err(Error::UnknownChunk {
+                offset: 48,
+                tag: [b'A', b'B', b'C', b'D']
+            })
+        ));
+        let missing_header = [&bytes[..12], &bytes[48..]].concat();
+        assert!(matches!(
+            Nif::from_bytes(&missing_header),
+            Err(Error::Corrupt { offset: 12, .. })
+        ));
+
+        //version 0.1 files are still read, and written back as version 0.1
+        let mut old = test_video(3);
+        old.version = VERSION_0_1;
+        for features in [0, records] {
+            let old_bytes = old.to_bytes(features).unwrap();
+            assert_eq!(&old_bytes[4..8], &VERSION_0_1.to_be_bytes());
+            let read = Nif::from_bytes(&old_bytes).unwrap();
+            assert_eq!(read.version, VERSION_0_1);
+            assert_eq!(read.get_frames(), nif.get_frames());
+            assert_eq!(read.to_bytes(features).unwrap(), old_bytes);
+
+            let options = WriteOptions::new(features).version(VERSION_0_1);
+            let mut writer = NifWriter::new(Cursor::new(Vec::new()), nif.header, options).unwrap();
+            writer.push_frames(nif.get_frames()).unwrap();
+            assert_eq!(writer.finish().unwrap().into_inner(), old_bytes);
+        }
+
+        //without seeking the DATA chunk runs to the end of the stream
+        let mut writer = NifWriter::new_streaming(Vec::new(), nif.header, records).unwrap();
+        writer.push_frames(nif.get_frames()).unwrap();
+        let streamed = writer.finish().unwrap();
+        assert_eq!(&streamed[52..60], &[0xFF; 8]);
+        let mut reader = NifReader::new(Cursor::new(&streamed)).unwrap();
+        assert_eq!(&reader.read_frame_at(1).unwrap(), &nif.get_frames()[1]);
+        assert_eq!(
+            Nif::from_bytes(&streamed).unwrap().get_frames(),
+            nif.get_frames()
+        );
+    }
+
+    #[test]
+    fn test_metadata() {
+        use crate::nif::{Metadata, METADATA_AUTHOR, METADATA_SOFTWARE, METADATA_TITLE};
+        use std::io::Cursor;
+        let mut nif = test_video(2);
+        let metadata = nif.metadata_mut();
+        metadata.set(METADATA_TITLE, "Sunset");
+        metadata.set(METADATA_AUTHOR, "Ana Pérez");
+        metadata.set(METADATA_SOFTWARE, "nif-test 1.0");
+        metadata.set(METADATA_TITLE, "Sunrise");
+        assert_eq!(nif.metadata().len(), 3);
+        assert_eq!(nif.metadata().get(METADATA_TITLE), Some("Sunrise"));
+
+        let bytes = nif.to_bytes(FEATURE_FLAGS_COMPRESSION).unwrap();
+        //the meta chunk comes right after the header
+        assert_eq!(&bytes[48..52], b"meta");
+        let read = Nif::from_bytes(&bytes).unwrap();
+        assert_eq!(read.metadata(), nif.metadata());
+        assert_eq!(read.get_frames(), nif.get_frames());
+        assert_eq!(read.to_bytes(FEATURE_FLAGS_COMPRESSION).unwrap(), bytes);
+        let reader = NifReader::new(Cursor::new(&bytes)).unwrap();
+        let keys: Vec<_> = reader.metadata().iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, [METADATA_TITLE, METADATA_AUTHOR, METADATA_SOFTWARE]);
+
+        //files without the chunk have no metadata
+        let plain = test_video(2).to_bytes(0).unwrap();
+        assert_eq!(
+            Nif::from_bytes(&plain).unwrap().metadata(),
+            &Metadata::new()
+        );
+        //version 0.1 has nowhere to store it
+        nif.version = VERSION_0_1;
+        assert!(matches!(
+            nif.to_bytes(0),
+            Err(Error::Unsupported { offset: 4, .. })
+        ));
+        *nif.metadata_mut() = Metadata::new();
+        assert!(nif.to_bytes(0).is_ok());
+
+        //the chunk data starts at 60 with the length of the first key
+        let mut bad = bytes.clone();
+        bad[64] = 0xFF;
+        assert!(matches!(
+            Nif::from_bytes(&bad),
+            Err(Error::Corrupt { offset: 60, .. })
+        ));
+        let mut truncated = bytes.clone();
+        truncated[63] += 100;
+        assert!(matches!(
+            Nif::from_bytes(&truncated),
+            Err(Error::Corrupt { offset: 60, .. })
+        ));
+    }
+
+    #[test]
+    fn test_color_space() {
+        use crate::nif::{ColorSpace, CURRENT_VERSION};
+        //files without a colr chunk are sRGB, version 0.1 files included
+        let mut nif = test_video(2);
+        let plain = nif.to_bytes(0).unwrap();
+        assert_eq!(&plain[48..52], b"DATA");
+        assert_eq!(
+            Nif::from_bytes(&plain).unwrap().color_space(),
+            ColorSpace::SRGB
+        );
+        nif.version = VERSION_0_1;
+        let old = Nif::from_bytes(&nif.to_bytes(0).unwrap()).unwrap();
+        assert_eq!(old.color_space(), ColorSpace::SRGB);
+        assert_eq!(old.icc_profile(), None);
+
+        let profile: Vec<u8> = (0..=255).collect();
+        nif.set_color_space(ColorSpace::REC2100_PQ);
+        nif.set_icc_profile(Some(profile.clone()));
+        assert!(matches!(
+            nif.to_bytes(0),
+            Err(Error::Unsupported { offset: 4, .. })
+        ));
+        nif.version = CURRENT_VERSION;
+        for space in [ColorSpace::DISPLAY_P3, ColorSpace::REC2100_PQ] {
+            nif.set_color_space(space);
+            let bytes = nif.to_bytes(FEATURE_FLAGS_COMPRESSION).unwrap();
+            assert_eq!(&bytes[48..52], b"colr");
+            let read = Nif::from_bytes(&bytes).unwrap();
+            assert_eq!(read.color_space(), space);
+            assert_eq!(read.icc_profile(), Some(&profile[..]));
+            assert_eq!(read.get_frames(), nif.get_frames());
+            assert_eq!(read.to_bytes(FEATURE_FLAGS_COMPRESSION).unwrap(), bytes);
+        }
+
+        //the chunk data starts at 60, unknown codes are ignored
+        let mut bytes = nif.to_bytes(0).unwrap();
+        for (at, code) in [(61, 200), (60, 7)] {
+            bytes[at] = code;
+            let read = Nif::from_bytes(&bytes).unwrap();
+            assert_eq!(read.color_space(), ColorSpace::SRGB);
+            assert_eq!(read.get_frames(), nif.get_frames());
+        }
+        //but the chunk must still be two bytes long
+        bytes[59] = 3;
+        assert!(matches!(
+            Nif::from_bytes(&bytes),
+            Err(Error::Corrupt { offset: 60, .. })
+        ));
+    }
+
+    #[test]
+    fn test_exif() {
+        use crate::nif::Orientation;
+        //a TIFF header and an IFD with the image width and the orientation
+        let tiff = |big_endian: bool, orientation: u16| {
+            let u16_bytes = |v: u16| match big_endian {
+                true => v.to_be_bytes(),
+                false => v.to_le_bytes(),
+            };
+            let u32_bytes = |v: u32| match big_endian {
+                true => v.to_be_bytes(),
+                false => v.to_le_bytes(),
+            };
+            let mut out = match big_endian {
+                true => b"MM\0*".to_vec(),
+                false => b"II*\0".to_vec(),
+            };
+            out.extend_from_slice(&u32_bytes(8));
+            out.extend_from_slice(&u16_bytes(2));
+            for (tag, kind, value) in [(0x0100, 4, 64), (0x0112, 3, orientation)] {
+                out.extend_from_slice(&u16_bytes(tag));
+                out.extend_from_slice(&u16_bytes(kind));
+                out.extend_from_slice(&u32_bytes(1));
+                let mut value_bytes = [0; 4];
+                match kind {
+                    3 => value_bytes[..2].copy_from_slice(&u16_bytes(value)),
+                    _ => value_bytes = u32_bytes(value as u32),
+                }
+                out.extend_from_slice(&value_bytes);
+            }
+            out.extend_from_slice(&u32_bytes(0));
+            out
+        };
+
+        let mut nif = test_video(1);
+        assert_eq!(nif.orientation(), Orientation::Normal);
+        let exif = tiff(true, 6);
+        let xmp = br#"<x:xmpmeta xmlns:x="adobe:ns:meta/"></x:xmpmeta>"#.to_vec();
+        nif.set_exif(Some(exif.clone()));
+        nif.set_xmp(Some(xmp.clone()));
+        assert_eq!(nif.orientation(), Orientation::Rotate90);
+        let bytes = nif.to_bytes(0).unwrap();
+        let read = Nif::from_bytes(&bytes).unwrap();
+        assert_eq!(read.exif(), Some(&exif[..]));
+        assert_eq!(read.xmp(), Some(&xmp[..]));
+        assert_eq!(read.orientation(), Orientation::Rotate90);
+        assert_eq!(read.get_frames(), nif.get_frames());
+        assert_eq!(read.to_bytes(0).unwrap(), bytes);
+
+        //little-endian data and the prefix of a JPEG APP1 segment
+        nif.set_exif(Some([&b"Exif\0\0"[..], &tiff(false, 3)].concat()));
+        assert_eq!(nif.orientation(), Orientation::Rotate180);
+        //values out of range and malformed data are ignored
+        nif.set_exif(Some(tiff(false, 9)));
+        assert_eq!(nif.orientation(), Orientation::Normal);
+        nif.set_exif(Some(tiff(true, 6)[..20].to_vec()));
+        assert_eq!(nif.orientation(), Orientation::Normal);
+
+        nif.version = VERSION_0_1;
+        assert!(matches!(
+            nif.to_bytes(0),
+            Err(Error::Unsupported { offset: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn test_frame_rate() {
+        use crate::nif::{CURRENT_VERSION, VERSION_0_2};
+        //legacy frame rates become the simplest fraction with the same f32
+        assert_eq!(Rational::from_f32(24.0), Rational::new(24, 1));
+        assert_eq!(Rational::from_f32(29.97), Rational::new(2997, 100));
+        assert_eq!(Rational::from_f32(0.0), Rational::new(0, 1));
+        assert_eq!(Rational::from_f32(f32::NAN), Rational::new(0, 1));
+        assert_eq!(Rational::from_f32(1e-12), Rational::new(0, 1));
+        for value in [30000.0 / 1001.0, 0.1, 1.0 / 3.0, 12345.678] {
+            let rate = Rational::from_f32(value);
+            assert_eq!(rate.to_f64() as f32, value, "{value}");
+        }
+
+        let ntsc = Rational::new(30000, 1001);
+        let mut nif = test_video(2);
+        nif.header.rate = ntsc;
+        assert_eq!(nif.header.frame_rate(), 29.97003);
+        let bytes = nif.to_bytes(0).unwrap();
+        assert_eq!(&bytes[16..24], &24_u64.to_be_bytes());
+        assert_eq!(&bytes[40..48], &[0, 0, 0x75, 0x30, 0, 0, 0x03, 0xE9]);
+        let read = Nif::from_bytes(&bytes).unwrap();
+        assert_eq!(read.header.rate, ntsc);
+        assert_eq!(read.get_frames(), nif.get_frames());
+
+        //older versions store the f32 and read it back as a fraction
+        for version in [VERSION_0_1, VERSION_0_2] {
+            nif.version = version;
+            let bytes = nif.to_bytes(0).unwrap();
+            let read = Nif::from_bytes(&bytes).unwrap();
+            assert_eq!(read.header.frame_rate(), nif.header.frame_rate());
+            assert_eq!(read.to_bytes(0).unwrap(), bytes);
+        }
+
+        let mut corrupt = bytes.clone();
+        corrupt[44..48].fill(0);
+        assert!(matches!(
+            Nif::from_bytes(&corrupt),
+            Err(Error::Corrupt { offset: 44, .. })
+        ));
+        nif.version = CURRENT_VERSION;
+        nif.header.rate = Rational::new(30, 0);
+        assert!(matches!(
+            nif.to_bytes(0),
+            Err(Error::Unsupported { offset: 44, .. })
+        ));
+    }
+
+    #[test]
+    fn test_timestamps() {
+        use crate::nif::{Frame, Rational, Timestamp};
+        use std::io::Cursor;
+        let millis = Rational::new(1, 1000);
+        let mut nif = test_video(4);
+        for (frame, ticks) in nif.get_frames_mut().iter_mut().zip([0, 33, 100, 250]) {
+            frame.set_timestamp(Some(Timestamp::new(ticks, millis)));
+        }
+        assert_eq!(nif.get_frames()[2].timestamp().unwrap().seconds(), 0.1);
+        let records = FEATURE_FLAGS_FRAME_INDEX | FEATURE_FLAGS_COMPRESSION;
+        for features in [0, records] {
+            let bytes = nif.to_bytes(features).unwrap();
+            assert_eq!(&bytes[48..52], b"time");
+            let read = Nif::from_bytes(&bytes).unwrap();
+            assert_eq!(read.get_frames(), nif.get_frames());
+            assert_eq!(read.to_bytes(features).unwrap(), bytes);
+
+            let mut reader = NifReader::new(Cursor::new(&bytes)).unwrap();
+            assert_eq!(reader.timebase(), Some(millis));
+            assert_eq!(reader.frame_index_at(-1.0), Some(0));
+            assert_eq!(reader.frame_index_at(0.05), Some(1));
+            assert_eq!(reader.frame_index_at(0.1), Some(2));
+            assert_eq!(reader.frame_index_at(60.0), Some(3));
+            assert_eq!(
+                &reader.read_frame_at_time(0.2).unwrap(),
+                &nif.get_frames()[2]
+            );
+        }
+
+        //without timestamps the frame rate is used
+        let plain = test_video(4).to_bytes(0).unwrap();
+        let mut reader = NifReader::new(Cursor::new(&plain)).unwrap();
+        assert_eq!(reader.timebase(), None);
+        assert_eq!(reader.frame_index_at(0.04), Some(2));
+        assert_eq!(reader.frame_index_at(1.0), Some(3));
+        let frame = reader.read_frame_at_time(0.02).unwrap();
+        assert_eq!(frame.timestamp(), None);
+        assert_eq!(frame.data, test_video(4).get_frames()[1].data);
+
+        //timestamps are all or nothing, in one timebase, and never decrease
+        let mut missing = test_video(2);
+        missing.get_frames_mut()[1].set_timestamp(Some(Timestamp::new(5, millis)));
+        assert!(matches!(
+            missing.to_bytes(0),
+            Err(Error::InvalidTimestamp { index: 1, .. })
+        ));
+
+        //frames written one at a time get their timestamps after the frames
+        let irregular = [0, 7, 7, 90];
+        for features in [0, records] {
+            let mut writer = NifWriter::new(Cursor::new(Vec::new()), nif.header, features).unwrap();
+            let frames: Vec<_> = nif
+                .get_frames()
+                .iter()
+                .zip(irregular)
+                .map(|(frame, ticks)| {
+                    let mut frame = Frame::from(frame.data.clone());
+                    frame.set_timestamp(Some(Timestamp::new(ticks, millis)));
+                    frame
+                })
+                .collect();
+            writer.push_frame(&frames[0]).unwrap();
+            writer.push_frames(&frames[1..]).unwrap();
+            let bytes = writer.finish().unwrap().into_inner();
+            assert_eq!(&bytes[bytes.len() - 52..bytes.len() - 48], b"time");
+            let read = Nif::from_bytes(&bytes).unwrap();
+            let ticks: Vec<_> = read
+                .get_frames()
+                .iter()
+                .map(|frame| frame.timestamp().unwrap().ticks)
+                .collect();
+            assert_eq!(ticks, irregular);
+
+            let mut reader = NifReader::new(Cursor::new(&bytes)).unwrap();
+            assert_eq!(reader.timebase(), None);
+            let frame = reader.read_frame_at_time(0.05).unwrap();
+            assert_eq!(frame.data, nif.get_frames()[2].data);
+            assert_eq!(frame.timestamp(), Some(Timestamp::new(7, millis)));
+            assert_eq!(reader.timebase(), Some(millis));
+        }
+        let mut streaming = NifWriter::new_streaming(Vec::new(), nif.header, 0).unwrap();
+        assert!(matches!(
+            streaming.push_frame(&nif.get_frames()[0]),
+            Err(Error::Unsupported { .. })
+        ));
+
+        let mut decreasing = nif;
+        decreasing.get_frames_mut()[3].set_timestamp(Some(Timestamp::new(99, millis)));
+        assert!(matches!(
+            decreasing.to_bytes(0),
+            Err(Error::InvalidTimestamp { index: 3, .. })
+        ));
+        decreasing.get_frames_mut()[3]
+            .set_timestamp(Some(Timestamp::new(300, Rational::new(1, 60))));
+        assert!(matches!(
+            decreasing.to_bytes(0),
+            Err(Error::InvalidTimestamp { index: 3, .. })
+        ));
+        decreasing.get_frames_mut()[3].set_timestamp(Some(Timestamp::new(300, millis)));
+        decreasing.version = VERSION_0_1;
+        assert!(matches!(
+            decreasing.to_bytes(0),
+            Err(Error::Unsupported { offset: 4, .. })
+        ));
+    }
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mapped_frames() {
+        use crate::nif::{MappedNif, MappedNifMut};
+        let nif = test_video(4);
+        let path = std::env::temp_dir().join("nif_test_mapped.nvf");
+        nif.write(&path, 0).unwrap();
+
+        //the file is private to this test and not changed while mapped
+        let mapped = unsafe { MappedNif::open(&path) }.unwrap();
+        assert_eq!(mapped.frame_count(), 4);
+        let hd = *mapped.header();
+        for (frame, expected) in mapped.frames().zip(nif.get_frames()) {
+            assert_eq!(frame.data(), &expected.data[..]);
+            assert_eq!(frame.get_pixel(3, 2, hd), expected.get_pixel(3, 2, hd));
+            assert!(frame.pixels(&hd).eq(expected.pixels(&hd)));
+        }
+        assert!(mapped.frame(4).is_none());
+        drop(mapped);
+
+        //edit a pixel in place and read it back through the normal reader
+        let red = Pixel::RGBA8888(Pixel32U::from_rgba(0xFF, 0, 0, 0xFF));
+        let mut mapped = unsafe { MappedNifMut::open(&path) }.unwrap();
+        mapped.frame_mut(2).unwrap().set_pixel(1, 1, red, hd);
+        mapped.flush().unwrap();
+        drop(mapped);
+        let mut nif_read = Nif::new_default();
+        nif_read.read_from_file(&path).unwrap();
+        assert_eq!(nif_read.get_frames()[2].get_pixel(1, 1, hd), red);
+        assert_eq!(nif_read.get_frames()[1], nif.get_frames()[1]);
+
+        nif.write(&path, FEATURE_FLAGS_COMPRESSION).unwrap();
+        assert!(matches!(
+            unsafe { MappedNif::open(&path) },
+            Err(Error::Unsupported { .. })
+        ));
+
+        //a 32-byte file whose frame count times frame size overflows
+        let mut huge = Nif::new(Header {
+            width: 1 << 16,
+            height: 1 << 16,
+            pixel_format: Pixel::RGBA8888(0.into()),
+            frame_count: 0,
+            rate: Rational::new(0, 1),
+        });
+        huge.version = VERSION_0_1;
+        let mut bytes = huge.to_bytes(0).unwrap();
+        assert_eq!(bytes.len(), 32);
+        bytes[24..28].copy_from_slice(&(1_u32 << 30).to_be_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(
+            unsafe { MappedNif::open(&path) },
+            Err(Error::DimensionOverflow { .. })
+        ));
+        std::fs::remove_file(&path).unwrap();
     }
 }
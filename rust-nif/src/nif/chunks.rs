@@ -0,0 +1,126 @@
+//The chunked container of version 0.2.
+//
+//After the magic number, version and feature flags a version 0.2 stream is a
+//list of chunks. A chunk is a four letter ASCII tag and the length of its
+//data (u64), followed by the data. Chunks whose tag starts with a lowercase
+//letter are ancillary, readers skip the ones they do not know and fail on
+//any other chunk they do not know. The HEAD chunk holding the header comes
+//first, and the DATA chunk holds the frames laid out like the payload of
+//version 0.1. Writers that cannot seek back store LENGTH_UNKNOWN as the
+//length of the DATA chunk, which then runs to the end of the stream. Only
+//ancillary chunks may follow the DATA chunk.
+use std::io::{self, Read, Write};
+
+use super::counting::Counting;
+use super::{Error, Result};
+
+pub(crate) type Tag = [u8; 4];
+
+pub(crate) const CHUNK_HEADER_SIZE: u64 = 12;
+pub(crate) const CHUNK_HEAD: Tag = *b"HEAD";
+pub(crate) const CHUNK_DATA: Tag = *b"DATA";
+//key/value metadata, see metadata.rs
+pub(crate) const CHUNK_META: Tag = *b"meta";
+//the color space and ICC profile, see color.rs
+pub(crate) const CHUNK_COLOR: Tag = *b"colr";
+pub(crate) const CHUNK_ICC: Tag = *b"iccp";
+//EXIF data and an XMP packet, see exif.rs
+pub(crate) const CHUNK_EXIF: Tag = *b"exif";
+pub(crate) const CHUNK_XMP: Tag = *b"xmp ";
+//per-frame timestamps, see time.rs
+pub(crate) const CHUNK_TIME: Tag = *b"time";
+pub(crate) const LENGTH_UNKNOWN: u64 = u64::MAX;
+
+pub(crate) fn is_ancillary(tag: Tag) -> bool {
+    tag[0].is_ascii_lowercase()
+}
+
+pub(crate) fn write_chunk_header<W: Write>(
+    buf: &mut Counting<W>,
+    tag: Tag,
+    length: u64,
+) -> Result<()> {
+    buf.write_checked(&tag)?;
+    buf.write_checked(&length.to_be_bytes())
+}
+
+pub(crate) fn write_chunk<W: Write>(buf: &mut Counting<W>, tag: Tag, data: &[u8]) -> Result<()> {
+    write_chunk_header(buf, tag, data.len() as u64)?;
+    buf.write_checked(data)
+}
+
+//None if the stream ends right before the chunk
+pub(crate) fn read_chunk_header<R: Read>(buf: &mut Counting<R>) -> Result<Option<(Tag, u64)>> {
+    let mut tag = [0; 4];
+    let position = buf.position();
+    let read = buf
+        .read(&mut tag[..1])
+        .map_err(|e| Error::from_io(e, position))?;
+    if read == 0 {
+        return Ok(None);
+    }
+    buf.read_checked(&mut tag[1..])?;
+    let mut length = [0; 8];
+    buf.read_checked(&mut length)?;
+    Ok(Some((tag, u64::from_be_bytes(length))))
+}
+
+pub(crate) fn read_chunk_data<R: Read>(buf: &mut Counting<R>, length: u64) -> Result<Vec<u8>> {
+    let position = buf.position();
+    let mut data = Vec::new();
+    buf.by_ref()
+        .take(length)
+        .read_to_end(&mut data)
+        .map_err(|e| Error::from_io(e, position))?;
+    if (data.len() as u64) < length {
+        return Err(Error::Truncated {
+            offset: buf.position(),
+        });
+    }
+    Ok(data)
+}
+
+pub(crate) fn skip_chunk_data<R: Read>(buf: &mut Counting<R>, length: u64) -> Result<()> {
+    let position = buf.position();
+    let skipped = io::copy(&mut buf.by_ref().take(length), &mut io::sink())
+        .map_err(|e| Error::from_io(e, position))?;
+    if skipped < length {
+        return Err(Error::Truncated {
+            offset: buf.position(),
+        });
+    }
+    Ok(())
+}
+
+//checks the chunks after the DATA chunk, which must all be ancillary
+#[cfg(feature = "mmap")]
+pub(crate) fn skip_trailing_chunks<R: Read>(buf: &mut Counting<R>) -> Result<()> {
+    read_trailing_chunks(buf, |_, _, _, _| Ok(false))
+}
+
+//like skip_trailing_chunks, but hands every chunk to `read` first with its
+//tag, length and offset. `read` returns false for chunks it leaves to be
+//skipped
+pub(crate) fn read_trailing_chunks<R: Read>(
+    buf: &mut Counting<R>,
+    mut read: impl FnMut(&mut Counting<R>, Tag, u64, u64) -> Result<bool>,
+) -> Result<()> {
+    loop {
+        let offset = buf.position();
+        let Some((tag, length)) = read_chunk_header(buf)? else {
+            return Ok(());
+        };
+        if tag == CHUNK_HEAD || tag == CHUNK_DATA {
+            return Err(Error::Corrupt {
+                offset,
+                reason: "misplaced chunk",
+            });
+        }
+        if !is_ancillary(tag) {
+            return Err(Error::UnknownChunk { offset, tag });
+        }
+        if !read(buf, tag, length, offset)? {
+            skip_chunk_data(buf, length)?;
+        }
+    }
+}
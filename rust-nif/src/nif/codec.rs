@@ -0,0 +1,226 @@
+//Compression codecs. With FEATURE_FLAGS_COMPRESSION set the codec id is
+//stored in the FEATURE_FLAGS_CODEC_MASK bits of the feature flags. A payload
+//compressed as a single stream is always gzip, every other codec compresses
+//frame records.
+use std::io::{self, Read, Write};
+
+use flate2::{
+    read::{DeflateDecoder, GzDecoder},
+    write::{DeflateEncoder, GzEncoder},
+    Compression,
+};
+
+use super::qoi;
+use super::{
+    Error, Result, FEATURE_FLAGS_CODEC_MASK, FEATURE_FLAGS_CODEC_SHIFT, FEATURE_FLAGS_COMPRESSION,
+    FEATURE_FLAGS_FRAME_INDEX,
+};
+
+pub const CODEC_GZIP: u32 = 0;
+pub const CODEC_DEFLATE: u32 = 1;
+pub const CODEC_ZSTD: u32 = 2;
+pub const CODEC_LZ4: u32 = 3;
+pub const CODEC_QOI: u32 = 4;
+//highest codec id defined by the format
+const CODEC_LAST: u32 = CODEC_QOI;
+
+/// A compression algorithm for frame data.
+///
+/// Each call compresses or decompresses one complete buffer, so codecs never
+/// see more than one frame at a time.
+///
+/// Streams can only use the codecs defined by the format, which are the
+/// implementations in this module. Implementing the trait elsewhere does not
+/// make a codec available to readers and writers.
+pub trait Codec: Send + Sync {
+    //the id stored in the feature flags
+    fn id(&self) -> u32;
+    //`level` is codec specific, None selects the codec's default
+    fn compress(&self, data: &[u8], level: Option<i32>) -> io::Result<Vec<u8>>;
+    //compresses data made of `pixel_size` byte pixels, for codecs that
+    //model pixels rather than bytes
+    fn compress_pixels(
+        &self,
+        data: &[u8],
+        _pixel_size: usize,
+        level: Option<i32>,
+    ) -> io::Result<Vec<u8>> {
+        self.compress(data, level)
+    }
+    //fails unless `data` decompresses to exactly `len` bytes, without ever
+    //allocating much more than that
+    fn decompress(&self, data: &[u8], len: usize) -> io::Result<Vec<u8>>;
+}
+
+pub struct Gzip;
+pub struct Deflate;
+#[cfg(feature = "zstd")]
+pub struct Zstd;
+//lz4 has no compression levels, the level is ignored
+#[cfg(feature = "lz4")]
+pub struct Lz4;
+//a fast lossless pixel codec in the style of QOI, without compression levels
+pub struct Qoi;
+
+//the codec with `id`, if it is defined by the format and enabled in this
+//build
+pub fn find_codec(id: u32) -> Option<&'static dyn Codec> {
+    match id {
+        CODEC_GZIP => Some(&Gzip),
+        CODEC_DEFLATE => Some(&Deflate),
+        #[cfg(feature = "zstd")]
+        CODEC_ZSTD => Some(&Zstd),
+        #[cfg(feature = "lz4")]
+        CODEC_LZ4 => Some(&Lz4),
+        CODEC_QOI => Some(&Qoi),
+        _ => None,
+    }
+}
+
+//the codec selected by `features`, None if they do not include compression
+pub(crate) fn for_features(features: u32) -> Result<Option<&'static dyn Codec>> {
+    let id = (features & FEATURE_FLAGS_CODEC_MASK) >> FEATURE_FLAGS_CODEC_SHIFT;
+    if features & FEATURE_FLAGS_COMPRESSION == 0 {
+        if id != CODEC_GZIP {
+            //a codec without compression has no meaning
+            return Err(Error::UnknownFeatureFlags {
+                offset: 8,
+                flags: features & FEATURE_FLAGS_CODEC_MASK,
+            });
+        }
+        return Ok(None);
+    }
+    if id > CODEC_LAST {
+        return Err(Error::UnknownFeatureFlags {
+            offset: 8,
+            flags: features & FEATURE_FLAGS_CODEC_MASK,
+        });
+    }
+    if id != CODEC_GZIP && features & FEATURE_FLAGS_FRAME_INDEX == 0 {
+        return Err(Error::Unsupported {
+            offset: 8,
+            reason: "only gzip can compress the payload as a single stream",
+        });
+    }
+    match find_codec(id) {
+        Some(codec) => Ok(Some(codec)),
+        None => Err(Error::Unsupported {
+            offset: 8,
+            reason: "codec is not enabled in this build",
+        }),
+    }
+}
+
+//flate2 levels go from 0 to 9
+pub(crate) fn flate_level(level: Option<i32>) -> Compression {
+    match level {
+        Some(level) => Compression::new(level.clamp(0, 9) as u32),
+        None => Compression::default(),
+    }
+}
+
+fn check_len(out: Vec<u8>, len: usize) -> io::Result<Vec<u8>> {
+    if out.len() != len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "decompressed data has the wrong size",
+        ));
+    }
+    Ok(out)
+}
+
+//reads at most one byte more than `len` so corrupt data cannot make us
+//allocate more than a frame. The buffer grows as data arrives, as `len`
+//comes from a header that may be corrupt too
+fn read_limited<R: Read>(reader: R, len: usize) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    reader.take(len as u64 + 1).read_to_end(&mut out)?;
+    check_len(out, len)
+}
+
+impl Codec for Gzip {
+    fn id(&self) -> u32 {
+        CODEC_GZIP
+    }
+    fn compress(&self, data: &[u8], level: Option<i32>) -> io::Result<Vec<u8>> {
+        let mut encoder = GzEncoder::new(Vec::new(), flate_level(level));
+        encoder.write_all(data)?;
+        encoder.finish()
+    }
+    fn decompress(&self, data: &[u8], len: usize) -> io::Result<Vec<u8>> {
+        read_limited(GzDecoder::new(data), len)
+    }
+}
+
+impl Codec for Deflate {
+    fn id(&self) -> u32 {
+        CODEC_DEFLATE
+    }
+    fn compress(&self, data: &[u8], level: Option<i32>) -> io::Result<Vec<u8>> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), flate_level(level));
+        encoder.write_all(data)?;
+        encoder.finish()
+    }
+    fn decompress(&self, data: &[u8], len: usize) -> io::Result<Vec<u8>> {
+        read_limited(DeflateDecoder::new(data), len)
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl Codec for Zstd {
+    fn id(&self) -> u32 {
+        CODEC_ZSTD
+    }
+    fn compress(&self, data: &[u8], level: Option<i32>) -> io::Result<Vec<u8>> {
+        zstd::bulk::compress(data, level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL))
+    }
+    fn decompress(&self, data: &[u8], len: usize) -> io::Result<Vec<u8>> {
+        read_limited(zstd::stream::read::Decoder::with_buffer(data)?, len)
+    }
+}
+
+#[cfg(feature = "lz4")]
+impl Codec for Lz4 {
+    fn id(&self) -> u32 {
+        CODEC_LZ4
+    }
+    fn compress(&self, data: &[u8], _level: Option<i32>) -> io::Result<Vec<u8>> {
+        Ok(lz4_flex::block::compress(data))
+    }
+    fn decompress(&self, data: &[u8], len: usize) -> io::Result<Vec<u8>> {
+        //a byte of lz4 data makes at most 255 bytes, so the output buffer
+        //is only allocated for sizes the data can hold
+        if len > data.len().saturating_mul(255) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "decompressed size is too large for the data",
+            ));
+        }
+        let mut out = vec![0; len];
+        let written = lz4_flex::block::decompress_into(data, &mut out)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        out.truncate(written);
+        check_len(out, len)
+    }
+}
+
+impl Codec for Qoi {
+    fn id(&self) -> u32 {
+        CODEC_QOI
+    }
+    //without a pixel size the data is treated as 32-bit pixels
+    fn compress(&self, data: &[u8], _level: Option<i32>) -> io::Result<Vec<u8>> {
+        qoi::encode(data, 4)
+    }
+    fn compress_pixels(
+        &self,
+        data: &[u8],
+        pixel_size: usize,
+        _level: Option<i32>,
+    ) -> io::Result<Vec<u8>> {
+        qoi::encode(data, pixel_size)
+    }
+    fn decompress(&self, data: &[u8], len: usize) -> io::Result<Vec<u8>> {
+        qoi::decode(data, len)
+    }
+}
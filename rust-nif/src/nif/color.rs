@@ -0,0 +1,107 @@
+//The color space of the pixel values, stored in the `colr` chunk of version
+//0.2 and up, and the ICC profile stored in the `iccp` chunk.
+//
+//The `colr` chunk holds two bytes, the code of the primaries and the code of
+//the transfer function. Files without it, and all version 0.1 files, are
+//sRGB, and so are files whose chunk has a code this version does not know.
+//The `iccp` chunk holds an ICC profile as is.
+use super::{Error, Result};
+
+/// The red, green and blue primaries and white point of the pixel values.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ColorPrimaries {
+    //ITU-R BT.709, the primaries of sRGB
+    #[default]
+    Bt709,
+    DisplayP3,
+    //ITU-R BT.2020, also used by BT.2100
+    Bt2020,
+}
+
+/// How the stored values map to linear light.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum TransferFunction {
+    //IEC 61966-2-1, also used by Display P3
+    #[default]
+    Srgb,
+    Linear,
+    //ITU-R BT.709 and BT.2020
+    Bt709,
+    //SMPTE ST 2084, the perceptual quantizer of HDR10
+    Pq,
+    //hybrid log-gamma of ITU-R BT.2100
+    Hlg,
+}
+
+/// The color space the pixel values are in.
+///
+/// Viewers that understand ICC profiles should prefer the embedded profile
+/// when there is one.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct ColorSpace {
+    pub primaries: ColorPrimaries,
+    pub transfer: TransferFunction,
+}
+
+impl ColorSpace {
+    pub const SRGB: ColorSpace = ColorSpace::new(ColorPrimaries::Bt709, TransferFunction::Srgb);
+    pub const LINEAR_SRGB: ColorSpace =
+        ColorSpace::new(ColorPrimaries::Bt709, TransferFunction::Linear);
+    pub const DISPLAY_P3: ColorSpace =
+        ColorSpace::new(ColorPrimaries::DisplayP3, TransferFunction::Srgb);
+    pub const REC2020: ColorSpace =
+        ColorSpace::new(ColorPrimaries::Bt2020, TransferFunction::Bt709);
+    pub const REC2100_PQ: ColorSpace =
+        ColorSpace::new(ColorPrimaries::Bt2020, TransferFunction::Pq);
+    pub const REC2100_HLG: ColorSpace =
+        ColorSpace::new(ColorPrimaries::Bt2020, TransferFunction::Hlg);
+
+    pub const fn new(primaries: ColorPrimaries, transfer: TransferFunction) -> Self {
+        ColorSpace {
+            primaries,
+            transfer,
+        }
+    }
+}
+
+pub(crate) fn encode(color_space: ColorSpace) -> [u8; 2] {
+    let primaries = match color_space.primaries {
+        ColorPrimaries::Bt709 => 0,
+        ColorPrimaries::DisplayP3 => 1,
+        ColorPrimaries::Bt2020 => 2,
+    };
+    let transfer = match color_space.transfer {
+        TransferFunction::Srgb => 0,
+        TransferFunction::Linear => 1,
+        TransferFunction::Bt709 => 2,
+        TransferFunction::Pq => 3,
+        TransferFunction::Hlg => 4,
+    };
+    [primaries, transfer]
+}
+
+//`offset` is where the chunk data starts in the stream. The chunk is
+//ancillary, so unknown codes fall back to sRGB instead of failing the file
+pub(crate) fn decode(data: &[u8], offset: u64) -> Result<ColorSpace> {
+    let &[primaries, transfer] = data else {
+        return Err(Error::Corrupt {
+            offset,
+            reason: "malformed color space",
+        });
+    };
+    let primaries = match primaries {
+        0 => ColorPrimaries::Bt709,
+        1 => ColorPrimaries::DisplayP3,
+        2 => ColorPrimaries::Bt2020,
+        _ => return Ok(ColorSpace::SRGB),
+    };
+    let transfer = match transfer {
+        0 => TransferFunction::Srgb,
+        1 => TransferFunction::Linear,
+        2 => TransferFunction::Bt709,
+        3 => TransferFunction::Pq,
+        4 => TransferFunction::Hlg,
+        _ => return Ok(ColorSpace::SRGB),
+    };
+    Ok(ColorSpace::new(primaries, transfer))
+}
@@ -0,0 +1,138 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use super::{Error, Result};
+
+//Wraps a reader or writer and keeps track of how many bytes went through it,
+//so errors can report where in the stream they happened.
+pub(crate) struct Counting<T> {
+    inner: T,
+    position: u64,
+    //reads stop here as if the stream ended, used for the DATA chunk of
+    //version 0.2 streams
+    end: Option<u64>,
+}
+
+impl<T> Counting<T> {
+    pub(crate) fn new(inner: T) -> Self {
+        Self::at(inner, 0)
+    }
+    //starts counting from `position` instead of zero
+    pub(crate) fn at(inner: T, position: u64) -> Self {
+        Counting {
+            inner,
+            position,
+            end: None,
+        }
+    }
+    pub(crate) fn position(&self) -> u64 {
+        self.position
+    }
+    pub(crate) fn end(&self) -> Option<u64> {
+        self.end
+    }
+    pub(crate) fn set_end(&mut self, end: Option<u64>) {
+        self.end = end;
+    }
+    pub(crate) fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<R: Read> Counting<R> {
+    pub(crate) fn read_checked(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.read_exact(buf)
+            .map_err(|e| Error::from_io(e, self.position))
+    }
+}
+
+//reads exactly `len` bytes, growing the buffer as data arrives so a
+//corrupt header cannot make us allocate more than the stream holds
+pub(crate) fn read_vec<R: Read>(reader: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let data = read_up_to(reader, len)?;
+    if data.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(data)
+}
+
+//like read_vec, but returns fewer bytes if the stream ends early
+pub(crate) fn read_up_to<R: Read>(reader: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.take(len as u64).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+//true if the stream has no more bytes, consumes one byte otherwise
+pub(crate) fn at_eof<R: Read>(reader: &mut R) -> io::Result<bool> {
+    let mut byte = [0; 1];
+    loop {
+        match reader.read(&mut byte) {
+            Ok(read) => return Ok(read == 0),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+impl<R: Seek> Counting<R> {
+    //moves to `position`, relative to where counting started
+    pub(crate) fn seek_to(&mut self, position: u64) -> Result<()> {
+        let delta = position as i64 - self.position as i64;
+        self.inner
+            .seek(SeekFrom::Current(delta))
+            .map_err(|e| Error::from_io(e, self.position))?;
+        self.position = position;
+        Ok(())
+    }
+    //moves to `offset` bytes from the end of the underlying stream, or from
+    //the end set with set_end
+    pub(crate) fn seek_from_end(&mut self, offset: i64) -> Result<()> {
+        if let Some(end) = self.end {
+            let position = end.checked_add_signed(offset);
+            return self.seek_to(position.ok_or(Error::Truncated { offset: 0 })?);
+        }
+        let err = |e| Error::from_io(e, self.position);
+        //where counting started in terms of the underlying stream
+        let start = self.inner.stream_position().map_err(err)? - self.position;
+        let end = self.inner.seek(SeekFrom::End(offset)).map_err(err)?;
+        if end < start {
+            return Err(Error::Truncated { offset: 0 });
+        }
+        self.position = end - start;
+        Ok(())
+    }
+}
+
+impl<W: Write> Counting<W> {
+    pub(crate) fn write_checked(&mut self, buf: &[u8]) -> Result<()> {
+        self.write_all(buf)
+            .map_err(|e| Error::from_io(e, self.position))
+    }
+}
+
+impl<R: Read> Read for Counting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let buf = match self.end {
+            Some(end) => {
+                let left = end.saturating_sub(self.position);
+                let len = buf.len().min(usize::try_from(left).unwrap_or(usize::MAX));
+                &mut buf[..len]
+            }
+            None => buf,
+        };
+        let read = self.inner.read(buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<W: Write> Write for Counting<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.position += written as u64;
+        Ok(written)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
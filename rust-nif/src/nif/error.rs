@@ -0,0 +1,209 @@
+use std::{fmt, io};
+
+/// Errors produced while reading or writing a NIF stream.
+///
+/// Every variant carries the byte offset into the stream at which the
+/// problem was detected, so a bad upload can be reported precisely.
+#[derive(Debug)]
+pub enum Error {
+    //the underlying reader or writer failed
+    Io {
+        offset: u64,
+        source: io::Error,
+    },
+    //the first four bytes are not 0x4E494600
+    BadMagic {
+        offset: u64,
+        found: u32,
+    },
+    //the file was written by a newer version of the format
+    UnsupportedVersion {
+        offset: u64,
+        version: u32,
+    },
+    //the pixel storage format is not one of the four defined formats
+    UnknownPixelFormat {
+        offset: u64,
+        format: u32,
+    },
+    //the stream ended before the header or payload was complete
+    Truncated {
+        offset: u64,
+    },
+    //there are bytes after the last frame
+    TrailingData {
+        offset: u64,
+    },
+    //feature bits this implementation does not understand
+    UnknownFeatureFlags {
+        offset: u64,
+        flags: u32,
+    },
+    //width * height * pixel size does not fit in memory
+    DimensionOverflow {
+        offset: u64,
+        width: u32,
+        height: u32,
+    },
+    //a frame to be written does not match the size given by the header
+    FrameSizeMismatch {
+        offset: u64,
+        index: usize,
+        expected: usize,
+        found: usize,
+    },
+    //the header frame count does not match the number of frames written
+    FrameCountMismatch {
+        offset: u64,
+        expected: u32,
+        found: usize,
+    },
+    //a frame index past the end of the stream was requested
+    FrameOutOfRange {
+        offset: u64,
+        index: u32,
+        count: u32,
+    },
+    //a tile past the edge of a tiled frame was requested
+    TileOutOfRange {
+        offset: u64,
+        tx: u32,
+        ty: u32,
+        tiles_x: u32,
+        tiles_y: u32,
+    },
+    //the operation is not possible with the layout of this stream
+    Unsupported {
+        offset: u64,
+        reason: &'static str,
+    },
+    //the timestamps of the frames to be written cannot be stored
+    InvalidTimestamp {
+        offset: u64,
+        index: usize,
+        reason: &'static str,
+    },
+    //a chunk that readers must understand but this implementation does not
+    UnknownChunk {
+        offset: u64,
+        tag: [u8; 4],
+    },
+    //a frame record or the frame index is inconsistent
+    Corrupt {
+        offset: u64,
+        reason: &'static str,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    //converts an io error, turning an early EOF into `Truncated`
+    pub(crate) fn from_io(source: io::Error, offset: u64) -> Self {
+        if source.kind() == io::ErrorKind::UnexpectedEof {
+            Error::Truncated { offset }
+        } else {
+            Error::Io { offset, source }
+        }
+    }
+
+    /// The byte offset in the stream where reading or writing failed.
+    pub fn offset(&self) -> u64 {
+        match *self {
+            Error::Io { offset, .. }
+            | Error::BadMagic { offset, .. }
+            | Error::UnsupportedVersion { offset, .. }
+            | Error::UnknownPixelFormat { offset, .. }
+            | Error::Truncated { offset }
+            | Error::TrailingData { offset }
+            | Error::UnknownFeatureFlags { offset, .. }
+            | Error::DimensionOverflow { offset, .. }
+            | Error::FrameSizeMismatch { offset, .. }
+            | Error::FrameCountMismatch { offset, .. }
+            | Error::FrameOutOfRange { offset, .. }
+            | Error::TileOutOfRange { offset, .. }
+            | Error::Unsupported { offset, .. }
+            | Error::InvalidTimestamp { offset, .. }
+            | Error::UnknownChunk { offset, .. }
+            | Error::Corrupt { offset, .. } => offset,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { source, .. } => write!(f, "I/O error: {}", source)?,
+            Error::BadMagic { found, .. } => write!(
+                f,
+                "invalid magic number {:#010x}, this is not a NIF file",
+                found
+            )?,
+            Error::UnsupportedVersion { version, .. } => {
+                write!(f, "unsupported NIF version {:#010x}", version)?
+            }
+            Error::UnknownPixelFormat { format, .. } => {
+                write!(f, "unknown pixel format {}", format)?
+            }
+            Error::Truncated { .. } => write!(f, "unexpected end of stream")?,
+            Error::TrailingData { .. } => write!(f, "unexpected data after the last frame")?,
+            Error::UnknownFeatureFlags { flags, .. } => {
+                write!(f, "unknown feature flags {:#010x}", flags)?
+            }
+            Error::DimensionOverflow { width, height, .. } => {
+                write!(f, "frame dimensions {}x{} are too large", width, height)?
+            }
+            Error::FrameSizeMismatch {
+                index,
+                expected,
+                found,
+                ..
+            } => write!(
+                f,
+                "frame {} has {} bytes, the header requires {}",
+                index, found, expected
+            )?,
+            Error::FrameCountMismatch {
+                expected, found, ..
+            } => write!(
+                f,
+                "header announces {} frames but {} were written",
+                expected, found
+            )?,
+            Error::FrameOutOfRange { index, count, .. } => write!(
+                f,
+                "frame {} is out of range, the stream has {}",
+                index, count
+            )?,
+            Error::TileOutOfRange {
+                tx,
+                ty,
+                tiles_x,
+                tiles_y,
+                ..
+            } => write!(
+                f,
+                "tile ({}, {}) is out of range, the frame has {}x{} tiles",
+                tx, ty, tiles_x, tiles_y
+            )?,
+            Error::Unsupported { reason, .. } => write!(f, "unsupported operation: {}", reason)?,
+            Error::InvalidTimestamp { index, reason, .. } => {
+                write!(f, "invalid timestamp of frame {}: {}", index, reason)?
+            }
+            Error::UnknownChunk { tag, .. } => {
+                write!(f, "unknown chunk {:?}", String::from_utf8_lossy(tag))?
+            }
+            Error::Corrupt { reason, .. } => write!(f, "corrupt stream: {}", reason)?,
+        }
+        write!(f, " (at byte {})", self.offset())
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
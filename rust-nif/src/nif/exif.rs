@@ -0,0 +1,84 @@
+//Camera data carried with the image, stored as is in the `exif` and `xmp `
+//chunks of version 0.2 and up.
+//
+//The `exif` chunk holds EXIF data in TIFF layout, starting with the byte
+//order mark, like the eXIf chunk of PNG. The `Exif\0\0` prefix of a JPEG APP1
+//segment is accepted in front of it. The `xmp ` chunk holds an XMP packet.
+//Only the orientation is ever read from them.
+
+/// How the stored pixels must be turned to display the image upright, from
+/// EXIF tag 0x0112.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Orientation {
+    #[default]
+    Normal,
+    FlipHorizontal,
+    Rotate180,
+    FlipVertical,
+    //flipped along the top left to bottom right diagonal
+    Transpose,
+    //rotated 90 degrees clockwise
+    Rotate90,
+    //flipped along the top right to bottom left diagonal
+    Transverse,
+    //rotated 90 degrees counterclockwise
+    Rotate270,
+}
+
+impl Orientation {
+    //the value of the EXIF tag, 1 to 8
+    pub fn from_exif(value: u16) -> Option<Orientation> {
+        Some(match value {
+            1 => Orientation::Normal,
+            2 => Orientation::FlipHorizontal,
+            3 => Orientation::Rotate180,
+            4 => Orientation::FlipVertical,
+            5 => Orientation::Transpose,
+            6 => Orientation::Rotate90,
+            7 => Orientation::Transverse,
+            8 => Orientation::Rotate270,
+            _ => return None,
+        })
+    }
+}
+
+const TAG_ORIENTATION: u16 = 0x0112;
+const TYPE_SHORT: u16 = 3;
+
+//the orientation tag of the first IFD, None if there is none or the data is
+//malformed
+pub(crate) fn orientation(exif: &[u8]) -> Option<Orientation> {
+    let tiff = exif.strip_prefix(b"Exif\0\0").unwrap_or(exif);
+    let big_endian = match tiff.get(..4)? {
+        b"MM\0*" => true,
+        b"II*\0" => false,
+        _ => return None,
+    };
+    let u16_at = |at: usize| -> Option<u16> {
+        let bytes = tiff.get(at..at.checked_add(2)?)?.try_into().unwrap();
+        Some(match big_endian {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        })
+    };
+    let u32_at = |at: usize| -> Option<u32> {
+        let bytes = tiff.get(at..at.checked_add(4)?)?.try_into().unwrap();
+        Some(match big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    };
+    let ifd = u32_at(4)? as usize;
+    let entries = u16_at(ifd)?;
+    //every entry is the tag, type, count and value, 12 bytes
+    for i in 0..entries as usize {
+        let entry = ifd + 2 + i * 12;
+        if u16_at(entry)? == TAG_ORIENTATION {
+            if u16_at(entry + 2)? != TYPE_SHORT || u32_at(entry + 4)? != 1 {
+                return None;
+            }
+            return Orientation::from_exif(u16_at(entry + 8)?);
+        }
+    }
+    None
+}
@@ -0,0 +1,137 @@
+//PNG style row filters, used when FEATURE_FLAGS_FILTER is set.
+//
+//Every row of a frame is stored as a one byte filter type followed by the
+//filtered row. Filters predict each byte from the byte one pixel to the
+//left, the byte above and the byte above and to the left, where bytes
+//outside the frame are zero, and store the difference modulo 256. Rows do
+//not depend on other frames. The filter of each row is chosen by trying all
+//of them and keeping the one with the smallest sum of absolute differences.
+use super::Header;
+
+pub(crate) const FILTER_NONE: u8 = 0;
+pub(crate) const FILTER_SUB: u8 = 1;
+pub(crate) const FILTER_UP: u8 = 2;
+pub(crate) const FILTER_AVERAGE: u8 = 3;
+pub(crate) const FILTER_PAETH: u8 = 4;
+
+pub(crate) struct RowFilter {
+    //bytes per row
+    stride: usize,
+    //bytes per pixel
+    bpp: usize,
+    rows: usize,
+}
+
+impl RowFilter {
+    pub(crate) fn new(header: &Header) -> Self {
+        let bpp = header.pixel_format.get_size();
+        RowFilter {
+            stride: header.width as usize * bpp,
+            bpp,
+            rows: header.height as usize,
+        }
+    }
+
+    //size of a filtered frame, None if it does not fit in memory
+    pub(crate) fn filtered_size(&self) -> Option<usize> {
+        self.rows.checked_mul(self.stride.checked_add(1)?)
+    }
+
+    //`data` must be a whole frame
+    pub(crate) fn apply(&self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.rows * (self.stride + 1));
+        let mut candidate = vec![0; self.stride];
+        let mut best = vec![0; self.stride];
+        let zero = vec![0; self.stride];
+        for y in 0..self.rows {
+            let row = &data[y * self.stride..(y + 1) * self.stride];
+            let above = if y == 0 {
+                &zero[..]
+            } else {
+                &data[(y - 1) * self.stride..y * self.stride]
+            };
+            let mut best_filter = FILTER_NONE;
+            let mut best_score = u64::MAX;
+            for filter in [
+                FILTER_NONE,
+                FILTER_SUB,
+                FILTER_UP,
+                FILTER_AVERAGE,
+                FILTER_PAETH,
+            ] {
+                for (x, out) in candidate.iter_mut().enumerate() {
+                    *out = row[x].wrapping_sub(self.predict(filter, row, above, x));
+                }
+                let score = candidate
+                    .iter()
+                    .map(|&b| (b as i8).unsigned_abs() as u64)
+                    .sum();
+                if score < best_score {
+                    best_score = score;
+                    best_filter = filter;
+                    std::mem::swap(&mut best, &mut candidate);
+                }
+            }
+            out.push(best_filter);
+            out.extend_from_slice(&best);
+        }
+        out
+    }
+
+    //reverses the filters of every whole row in `data`, which is the whole
+    //frame once it is filtered_size bytes long. None if a row has an
+    //unknown filter type
+    pub(crate) fn reverse(&self, data: &[u8]) -> Option<Vec<u8>> {
+        let rows = self.rows.min(data.len() / (self.stride + 1));
+        let mut out = vec![0; rows * self.stride];
+        for y in 0..rows {
+            let filtered = &data[y * (self.stride + 1)..(y + 1) * (self.stride + 1)];
+            let filter = filtered[0];
+            if filter > FILTER_PAETH {
+                return None;
+            }
+            let (done, rest) = out.split_at_mut(y * self.stride);
+            let above = match y {
+                0 => &[][..],
+                _ => &done[(y - 1) * self.stride..],
+            };
+            let row = &mut rest[..self.stride];
+            for x in 0..self.stride {
+                row[x] = filtered[x + 1].wrapping_add(self.predict(filter, row, above, x));
+            }
+        }
+        Some(out)
+    }
+
+    //the prediction for byte `x` of `row`, `above` is empty for the first row
+    fn predict(&self, filter: u8, row: &[u8], above: &[u8], x: usize) -> u8 {
+        let a = if x >= self.bpp { row[x - self.bpp] } else { 0 };
+        let b = above.get(x).copied().unwrap_or(0);
+        let c = if x >= self.bpp {
+            above.get(x - self.bpp).copied().unwrap_or(0)
+        } else {
+            0
+        };
+        match filter {
+            FILTER_SUB => a,
+            FILTER_UP => b,
+            FILTER_AVERAGE => ((a as u16 + b as u16) / 2) as u8,
+            FILTER_PAETH => paeth(a, b, c),
+            _ => 0,
+        }
+    }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
@@ -0,0 +1,94 @@
+//Adam7 interlacing, used when FEATURE_FLAGS_INTERLACE is set.
+//
+//The pixels of a frame are split into seven passes like in PNG. Each pass is
+//a smaller image made of every pixel at (x0 + i * dx, y0 + j * dy), and the
+//passes are stored one after the other, each filtered or run-length encoded
+//as an image of its own. Passes without pixels are left out. The first pass
+//holds one pixel in 64, so a preview of the whole frame can be shown long
+//before the last pass arrives.
+use super::{resize_frame, Header, Result};
+
+//x0, y0, dx and dy of every pass
+const PASSES: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+//a header with the dimensions of every pass, both 0 for empty passes
+pub(crate) fn passes(header: &Header) -> Vec<Header> {
+    let count = |size: u32, start: u32, step: u32| size.saturating_sub(start).div_ceil(step);
+    PASSES
+        .iter()
+        .map(|&(x0, y0, dx, dy)| {
+            let (width, height) = (count(header.width, x0, dx), count(header.height, y0, dy));
+            let empty = width == 0 || height == 0;
+            Header {
+                width: if empty { 0 } else { width },
+                height: if empty { 0 } else { height },
+                ..*header
+            }
+        })
+        .collect()
+}
+
+//the pixels of every pass
+pub(crate) fn split(header: &Header, frame: &[u8]) -> Vec<Vec<u8>> {
+    let bpp = header.pixel_format.get_size();
+    let stride = header.width as usize * bpp;
+    passes(header)
+        .iter()
+        .zip(PASSES)
+        .map(|(pass, (x0, y0, dx, dy))| {
+            let mut out = Vec::with_capacity(pass.width as usize * pass.height as usize * bpp);
+            for j in 0..pass.height {
+                let row = (y0 + j * dy) as usize * stride;
+                for i in 0..pass.width {
+                    let start = row + (x0 + i * dx) as usize * bpp;
+                    out.extend_from_slice(&frame[start..start + bpp]);
+                }
+            }
+            out
+        })
+        .collect()
+}
+
+//puts the pixels of the passes back in place. With `fill` every pixel also
+//covers the pixels after it that later passes have not set, which turns
+//incomplete passes, holding fewer rows than they should, into a preview of
+//the whole frame
+pub(crate) fn merge(header: &Header, passes_data: &[Vec<u8>], fill: bool) -> Result<Vec<u8>> {
+    let bpp = header.pixel_format.get_size();
+    let stride = header.width as usize * bpp;
+    let mut frame = Vec::new();
+    resize_frame(&mut frame, header)?;
+    for (p, (pass, data)) in passes(header).iter().zip(passes_data).enumerate() {
+        let (x0, y0, dx, dy) = PASSES[p];
+        //the block a pixel covers until the next passes fill it in
+        let (block_w, block_h) = match fill {
+            true => (dx - x0.min(dx / 2), dy - y0.min(dy / 2)),
+            false => (1, 1),
+        };
+        let row_len = pass.width as usize * bpp;
+        if row_len == 0 {
+            continue;
+        }
+        for (j, row) in data.chunks_exact(row_len).enumerate() {
+            let y = y0 + j as u32 * dy;
+            for (i, pixel) in row.chunks_exact(bpp).enumerate() {
+                let x = x0 + i as u32 * dx;
+                for by in y..(y + block_h).min(header.height) {
+                    for bx in x..(x + block_w).min(header.width) {
+                        let start = by as usize * stride + bx as usize * bpp;
+                        frame[start..start + bpp].copy_from_slice(pixel);
+                    }
+                }
+            }
+        }
+    }
+    Ok(frame)
+}
@@ -0,0 +1,225 @@
+use std::{
+    fs::{File, OpenOptions},
+    ops::Range,
+    path::Path,
+};
+
+use memmap2::{Mmap, MmapMut};
+
+use super::chunks;
+use super::counting::Counting;
+use super::{
+    frame_size, is_chunked, read_prefix, Error, FrameMut, FrameRef, Header, Prefix, Result,
+    FEATURE_FLAGS_COMPRESSION, FEATURE_FLAGS_FRAME_INDEX, FEATURE_FLAGS_INTERLACE,
+    FRAME_COUNT_UNKNOWN,
+};
+
+//where the frames are inside a mapped file
+struct Layout {
+    version: u32,
+    features: u32,
+    //frame_count is the number of frames actually present, never FRAME_COUNT_UNKNOWN
+    header: Header,
+    frame_size: usize,
+    payload_start: usize,
+}
+
+impl Layout {
+    fn parse(bytes: &[u8]) -> Result<Layout> {
+        let mut buf = Counting::new(bytes);
+        let Prefix {
+            version,
+            features,
+            mut header,
+            ..
+        } = read_prefix(&mut buf)?;
+        if features & FEATURE_FLAGS_COMPRESSION != 0 {
+            return Err(Error::Unsupported {
+                offset: 8,
+                reason: "memory mapping a compressed file",
+            });
+        }
+        if features & FEATURE_FLAGS_FRAME_INDEX != 0 {
+            return Err(Error::Unsupported {
+                offset: 8,
+                reason: "memory mapping a file with frame records",
+            });
+        }
+        if features & FEATURE_FLAGS_INTERLACE != 0 {
+            return Err(Error::Unsupported {
+                offset: 8,
+                reason: "memory mapping an interlaced file",
+            });
+        }
+        let frame_size = frame_size(&header)?;
+        let payload_start = buf.position() as usize;
+        //the DATA chunk of a version 0.2 file may be followed by more chunks
+        let payload_end = match buf.end() {
+            Some(end) if end > bytes.len() as u64 => {
+                return Err(Error::Truncated {
+                    offset: bytes.len() as u64,
+                })
+            }
+            Some(end) => {
+                chunks::skip_trailing_chunks(&mut Counting::at(&bytes[end as usize..], end))?;
+                end as usize
+            }
+            None => bytes.len(),
+        };
+        let payload_len = payload_end - payload_start;
+        //older writers stored a frame count of 0 for an image of one frame
+        if !is_chunked(version) && header.frame_count == 0 && payload_len == frame_size {
+            header.frame_count = 1;
+        }
+        let unknown_count = header.frame_count == FRAME_COUNT_UNKNOWN;
+        if unknown_count {
+            header.frame_count = match payload_len.checked_div(frame_size) {
+                Some(count) if count < FRAME_COUNT_UNKNOWN as usize => count as u32,
+                Some(_) => {
+                    return Err(Error::DimensionOverflow {
+                        offset: 24,
+                        width: header.width,
+                        height: header.height,
+                    })
+                }
+                None => 0,
+            };
+        }
+        let expected = (header.frame_count as usize)
+            .checked_mul(frame_size)
+            .ok_or(Error::DimensionOverflow {
+                offset: 24,
+                width: header.width,
+                height: header.height,
+            })?;
+        //a partial frame at the end of a stream of unknown length
+        if payload_len < expected || unknown_count && expected < payload_len {
+            return Err(Error::Truncated {
+                offset: payload_end as u64,
+            });
+        }
+        if payload_len > expected {
+            return Err(Error::TrailingData {
+                offset: (payload_start + expected) as u64,
+            });
+        }
+        Ok(Layout {
+            version,
+            features,
+            header,
+            frame_size,
+            payload_start,
+        })
+    }
+
+    fn frame_range(&self, index: u32) -> Option<Range<usize>> {
+        if index >= self.header.frame_count {
+            return None;
+        }
+        let start = (index as usize)
+            .checked_mul(self.frame_size)?
+            .checked_add(self.payload_start)?;
+        Some(start..start.checked_add(self.frame_size)?)
+    }
+}
+
+/// A read-only memory map of an uncompressed NIF file.
+///
+/// Opening is O(1) regardless of the file size, frames are handed out as
+/// `FrameRef`s pointing straight into the mapped bytes.
+///
+/// The file must not be modified by anyone else while it is mapped.
+pub struct MappedNif {
+    map: Mmap,
+    layout: Layout,
+}
+
+impl MappedNif {
+    /// Maps the file at `path` and checks its header.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated, by this or any other
+    /// process, while it is mapped. Otherwise the frames handed out may
+    /// change under the borrow or point past the end of the map.
+    pub unsafe fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path).map_err(|e| Error::from_io(e, 0))?;
+        //Safety: upheld by the caller
+        let map = unsafe { Mmap::map(&file) }.map_err(|e| Error::from_io(e, 0))?;
+        let layout = Layout::parse(&map)?;
+        Ok(MappedNif { map, layout })
+    }
+
+    pub fn version(&self) -> u32 {
+        self.layout.version
+    }
+    pub fn features(&self) -> u32 {
+        self.layout.features
+    }
+    //the frame count is the number of frames in the file, also for files
+    //written with FRAME_COUNT_UNKNOWN
+    pub fn header(&self) -> &Header {
+        &self.layout.header
+    }
+    pub fn frame_count(&self) -> u32 {
+        self.layout.header.frame_count
+    }
+
+    pub fn frame(&self, index: u32) -> Option<FrameRef<'_>> {
+        let range = self.layout.frame_range(index)?;
+        Some(FrameRef::from(&self.map[range]))
+    }
+    pub fn frames(&self) -> impl Iterator<Item = FrameRef<'_>> {
+        (0..self.frame_count()).filter_map(move |index| self.frame(index))
+    }
+}
+
+/// A writable memory map of an uncompressed NIF file, for editing pixels
+/// in place. The header cannot be changed through the map.
+pub struct MappedNifMut {
+    map: MmapMut,
+    layout: Layout,
+}
+
+impl MappedNifMut {
+    /// Maps the file at `path` for writing and checks its header.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated by anyone else while it is
+    /// mapped, see [`MappedNif::open`].
+    pub unsafe fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|e| Error::from_io(e, 0))?;
+        //Safety: upheld by the caller
+        let map = unsafe { MmapMut::map_mut(&file) }.map_err(|e| Error::from_io(e, 0))?;
+        let layout = Layout::parse(&map)?;
+        Ok(MappedNifMut { map, layout })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.layout.header
+    }
+    pub fn frame_count(&self) -> u32 {
+        self.layout.header.frame_count
+    }
+
+    pub fn frame(&self, index: u32) -> Option<FrameRef<'_>> {
+        let range = self.layout.frame_range(index)?;
+        Some(FrameRef::from(&self.map[range]))
+    }
+    pub fn frame_mut(&mut self, index: u32) -> Option<FrameMut<'_>> {
+        let range = self.layout.frame_range(index)?;
+        Some(FrameMut::from(&mut self.map[range]))
+    }
+
+    //Writes modified pixels back to the file.
+    pub fn flush(&self) -> Result<()> {
+        self.map
+            .flush()
+            .map_err(|e| Error::from_io(e, self.layout.payload_start as u64))
+    }
+}
@@ -0,0 +1,106 @@
+//Key/value metadata, stored in the `meta` chunk of version 0.2 and up.
+//
+//The chunk holds every pair as the length of the key (u32), the key, the
+//length of the value (u32) and the value. Keys and values are UTF-8, and a
+//key appears at most once.
+use super::{Error, Result};
+
+//the name of the image or video
+pub const METADATA_TITLE: &str = "title";
+//who made the image or video
+pub const METADATA_AUTHOR: &str = "author";
+//when the image or video was made, as an RFC 3339 date and time such as
+//2024-05-01T12:00:00Z
+pub const METADATA_CREATED: &str = "created";
+//the name and version of the program that wrote the file
+pub const METADATA_SOFTWARE: &str = "software";
+
+/// UTF-8 key/value pairs stored with a NIF file, such as its title or author.
+///
+/// Pairs are kept in the order their keys were first set.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Metadata {
+    entries: Vec<(String, String)>,
+}
+
+impl Metadata {
+    pub fn new() -> Self {
+        Metadata::default()
+    }
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+    //replaces the value of a key that is already set
+    pub fn set(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        let key = key.into();
+        let value = value.into();
+        match self.entries.iter_mut().find(|(k, _)| *k == key) {
+            Some(entry) => entry.1 = value,
+            None => self.entries.push((key, value)),
+        }
+    }
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let index = self.entries.iter().position(|(k, _)| k == key)?;
+        Some(self.entries.remove(index).1)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+pub(crate) fn encode(metadata: &Metadata) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (key, value) in metadata.iter() {
+        for s in [key, value] {
+            out.extend_from_slice(&(s.len() as u32).to_be_bytes());
+            out.extend_from_slice(s.as_bytes());
+        }
+    }
+    out
+}
+
+//`offset` is where the chunk data starts in the stream
+pub(crate) fn decode(data: &[u8], offset: u64) -> Result<Metadata> {
+    let mut metadata = Metadata::new();
+    let mut position = 0;
+    while position < data.len() {
+        let start = offset + position as u64;
+        let key = read_string(data, &mut position, offset)?;
+        let value = read_string(data, &mut position, offset)?;
+        if metadata.get(&key).is_some() {
+            return Err(Error::Corrupt {
+                offset: start,
+                reason: "duplicate metadata key",
+            });
+        }
+        metadata.entries.push((key, value));
+    }
+    Ok(metadata)
+}
+
+//a length and the string following it, starting at `position`
+fn read_string(data: &[u8], position: &mut usize, offset: u64) -> Result<String> {
+    let corrupt = |reason| Error::Corrupt {
+        offset: offset + *position as u64,
+        reason,
+    };
+    let len = data
+        .get(*position..*position + 4)
+        .ok_or(corrupt("malformed metadata"))?;
+    let len = u32::from_be_bytes(len.try_into().unwrap()) as usize;
+    let bytes = data[*position + 4..]
+        .get(..len)
+        .ok_or(corrupt("malformed metadata"))?;
+    let s = String::from_utf8(bytes.to_vec()).map_err(|_| corrupt("metadata is not UTF-8"))?;
+    *position += 4 + len;
+    Ok(s)
+}
@@ -0,0 +1,171 @@
+//Mipmap levels, the downscaled copies of an image stored with
+//FEATURE_FLAGS_MIPMAPS.
+//
+//Level n is the image scaled to max(1, width >> n) by max(1, height >> n)
+//pixels, down to a single pixel. Every level is computed from the level
+//before it with a separable filter, channel by channel, rounding to the
+//nearest value.
+use super::Header;
+
+/// The filter used to compute each mipmap level from the one before it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum MipmapFilter {
+    //the average of the pixels covered by the new pixel
+    #[default]
+    Box,
+    //sharper than a box filter, may ring around hard edges
+    Lanczos3,
+}
+
+impl MipmapFilter {
+    //the filter's radius in pixels of the smaller image
+    fn support(self) -> f32 {
+        match self {
+            MipmapFilter::Box => 0.5,
+            MipmapFilter::Lanczos3 => 3.0,
+        }
+    }
+
+    fn weight(self, t: f32) -> f32 {
+        match self {
+            MipmapFilter::Box => {
+                if (-0.5..0.5).contains(&t) {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            MipmapFilter::Lanczos3 => {
+                if t.abs() >= 3.0 {
+                    0.0
+                } else {
+                    sinc(t) * sinc(t / 3.0)
+                }
+            }
+        }
+    }
+}
+
+fn sinc(t: f32) -> f32 {
+    if t == 0.0 {
+        1.0
+    } else {
+        let t = t * std::f32::consts::PI;
+        t.sin() / t
+    }
+}
+
+//channels of a pixel, bytes for 32-bit pixels and nibbles for 16-bit ones
+fn unpack(pixel: &[u8], out: &mut [f32]) {
+    match pixel.len() {
+        2 => {
+            for (i, byte) in pixel.iter().enumerate() {
+                out[i * 2] = (byte >> 4) as f32;
+                out[i * 2 + 1] = (byte & 0xF) as f32;
+            }
+        }
+        _ => {
+            for (out, &byte) in out.iter_mut().zip(pixel) {
+                *out = byte as f32;
+            }
+        }
+    }
+}
+
+fn pack(channels: &[f32], out: &mut [u8]) {
+    let max = if out.len() == 2 { 15.0 } else { 255.0 };
+    let channel = |c: f32| c.round().clamp(0.0, max) as u8;
+    match out.len() {
+        2 => {
+            for (i, byte) in out.iter_mut().enumerate() {
+                *byte = channel(channels[i * 2]) << 4 | channel(channels[i * 2 + 1]);
+            }
+        }
+        _ => {
+            for (byte, &c) in out.iter_mut().zip(channels) {
+                *byte = channel(c);
+            }
+        }
+    }
+}
+
+//for every pixel of a row scaled from `from` to `to` pixels, the pixels it
+//is computed from and their weights
+fn weights(filter: MipmapFilter, from: u32, to: u32) -> Vec<Vec<(usize, f32)>> {
+    let scale = from as f32 / to as f32;
+    let radius = filter.support() * scale;
+    (0..to)
+        .map(|x| {
+            let center = (x as f32 + 0.5) * scale;
+            let first = (center - radius).floor().max(0.0) as u32;
+            let last = ((center + radius).ceil() as u32).min(from);
+            let mut taps: Vec<(usize, f32)> = (first..last)
+                .map(|i| {
+                    let w = filter.weight((i as f32 + 0.5 - center) / scale);
+                    (i as usize, w)
+                })
+                .filter(|&(_, w)| w != 0.0)
+                .collect();
+            let sum: f32 = taps.iter().map(|&(_, w)| w).sum();
+            for tap in &mut taps {
+                tap.1 /= sum;
+            }
+            taps
+        })
+        .collect()
+}
+
+//the next smaller level of an image with the dimensions of `header`
+pub(crate) fn downscale(header: &Header, data: &[u8], filter: MipmapFilter) -> Vec<u8> {
+    let bpp = header.pixel_format.get_size();
+    let (width, height) = (header.width as usize, header.height as usize);
+    let next = header.level(1);
+    let (next_width, next_height) = (next.width as usize, next.height as usize);
+    let mut channels = vec![0.0; width * height * 4];
+    for (pixel, out) in data.chunks_exact(bpp).zip(channels.chunks_exact_mut(4)) {
+        unpack(pixel, out);
+    }
+    //rows first, then columns
+    let mut rows = vec![0.0; next_width * height * 4];
+    let across = weights(filter, header.width, next.width);
+    for y in 0..height {
+        for (x, taps) in across.iter().enumerate() {
+            let out = &mut rows[(y * next_width + x) * 4..][..4];
+            for &(i, w) in taps {
+                let pixel = &channels[(y * width + i) * 4..][..4];
+                for c in 0..4 {
+                    out[c] += pixel[c] * w;
+                }
+            }
+        }
+    }
+    let mut out = vec![0; next_width * next_height * bpp];
+    let down = weights(filter, header.height, next.height);
+    let mut pixel = [0.0; 4];
+    for (y, taps) in down.iter().enumerate() {
+        for x in 0..next_width {
+            pixel.fill(0.0);
+            for &(i, w) in taps {
+                let above = &rows[(i * next_width + x) * 4..][..4];
+                for c in 0..4 {
+                    pixel[c] += above[c] * w;
+                }
+            }
+            pack(&pixel, &mut out[(y * next_width + x) * bpp..][..bpp]);
+        }
+    }
+    out
+}
+
+//levels 1 and up of an image, smallest last
+pub(crate) fn levels(header: &Header, data: &[u8], filter: MipmapFilter) -> Vec<Vec<u8>> {
+    let mut levels: Vec<Vec<u8>> = Vec::new();
+    for n in 1..header.level_count() {
+        let data = match levels.last() {
+            Some(level) => downscale(&header.level(n - 1), level, filter),
+            None => downscale(header, data, filter),
+        };
+        levels.push(data);
+    }
+    levels
+}
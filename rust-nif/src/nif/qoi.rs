@@ -0,0 +1,179 @@
+//A lossless pixel codec in the style of QOI (https://qoiformat.org), used
+//for CODEC_QOI.
+//
+//A stream starts with the length of the data (u32) and the pixel size (u8,
+//2 or 4). Pixels are split into their R, G, B and A channels, bytes for
+//32-bit pixels and nibbles for 16-bit pixels, and encoded with the QOI ops
+//against the pixel before them, starting from all zero channels. Unlike QOI
+//the diff ops wrap around at 256 for nibbles too, which keeps them lossless.
+//Bytes after the last whole pixel follow the ops as they are.
+use std::io;
+
+const HEADER_SIZE: usize = 5;
+
+const OP_INDEX: u8 = 0x00;
+const OP_DIFF: u8 = 0x40;
+const OP_LUMA: u8 = 0x80;
+const OP_RUN: u8 = 0xC0;
+const OP_RGB: u8 = 0xFE;
+const OP_RGBA: u8 = 0xFF;
+const MASK_2: u8 = 0xC0;
+//runs of 63 and 64 would collide with OP_RGB and OP_RGBA
+const MAX_RUN: u8 = 62;
+
+type Channels = [u8; 4];
+
+fn hash(c: Channels) -> usize {
+    (c[0] as usize * 3 + c[1] as usize * 5 + c[2] as usize * 7 + c[3] as usize * 11) % 64
+}
+
+fn unpack(pixel: &[u8]) -> Channels {
+    match pixel.len() {
+        2 => [pixel[0] >> 4, pixel[0] & 0xF, pixel[1] >> 4, pixel[1] & 0xF],
+        _ => [pixel[0], pixel[1], pixel[2], pixel[3]],
+    }
+}
+
+fn pack(c: Channels, out: &mut [u8]) {
+    match out.len() {
+        2 => {
+            out[0] = c[0] << 4 | (c[1] & 0xF);
+            out[1] = c[2] << 4 | (c[3] & 0xF);
+        }
+        _ => out.copy_from_slice(&c),
+    }
+}
+
+fn invalid(reason: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+pub(crate) fn encode(data: &[u8], pixel_size: usize) -> io::Result<Vec<u8>> {
+    let len = u32::try_from(data.len()).map_err(|_| invalid("data larger than 4 GiB"))?;
+    let mut out = Vec::with_capacity(data.len() / 2 + HEADER_SIZE);
+    out.extend_from_slice(&len.to_be_bytes());
+    out.push(pixel_size as u8);
+    let mut index = [[0; 4]; 64];
+    let mut prev: Channels = [0; 4];
+    let mut run = 0;
+    let pixels = data.chunks_exact(pixel_size);
+    let rest = pixels.remainder();
+    for pixel in pixels {
+        let c = unpack(pixel);
+        if c == prev {
+            run += 1;
+            if run == MAX_RUN {
+                out.push(OP_RUN | (run - 1));
+                run = 0;
+            }
+            continue;
+        }
+        if run > 0 {
+            out.push(OP_RUN | (run - 1));
+            run = 0;
+        }
+        let h = hash(c);
+        if index[h] == c {
+            out.push(OP_INDEX | h as u8);
+        } else {
+            index[h] = c;
+            if c[3] == prev[3] {
+                let dr = c[0].wrapping_sub(prev[0]) as i8;
+                let dg = c[1].wrapping_sub(prev[1]) as i8;
+                let db = c[2].wrapping_sub(prev[2]) as i8;
+                let dr_dg = dr.wrapping_sub(dg);
+                let db_dg = db.wrapping_sub(dg);
+                if (-2..2).contains(&dr) && (-2..2).contains(&dg) && (-2..2).contains(&db) {
+                    out.push(
+                        OP_DIFF | ((dr + 2) as u8) << 4 | ((dg + 2) as u8) << 2 | (db + 2) as u8,
+                    );
+                } else if (-32..32).contains(&dg)
+                    && (-8..8).contains(&dr_dg)
+                    && (-8..8).contains(&db_dg)
+                {
+                    out.push(OP_LUMA | (dg + 32) as u8);
+                    out.push(((dr_dg + 8) as u8) << 4 | (db_dg + 8) as u8);
+                } else {
+                    out.extend_from_slice(&[OP_RGB, c[0], c[1], c[2]]);
+                }
+            } else {
+                out.extend_from_slice(&[OP_RGBA, c[0], c[1], c[2], c[3]]);
+            }
+        }
+        prev = c;
+    }
+    if run > 0 {
+        out.push(OP_RUN | (run - 1));
+    }
+    out.extend_from_slice(rest);
+    Ok(out)
+}
+
+//fails unless the stream holds exactly `len` bytes
+pub(crate) fn decode(data: &[u8], len: usize) -> io::Result<Vec<u8>> {
+    let header = data
+        .get(..HEADER_SIZE)
+        .ok_or_else(|| invalid("missing QOI header"))?;
+    let stored_len = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+    let pixel_size = header[4] as usize;
+    if stored_len != len {
+        return Err(invalid("decompressed data has the wrong size"));
+    }
+    if pixel_size != 2 && pixel_size != 4 {
+        return Err(invalid("unknown QOI pixel size"));
+    }
+    //every op makes at most MAX_RUN pixels, so the output buffer is only
+    //allocated for sizes the stream can hold
+    if len / pixel_size > (data.len() - HEADER_SIZE).saturating_mul(MAX_RUN as usize) {
+        return Err(invalid("QOI stream is too short for its size"));
+    }
+    let mut out = vec![0; len];
+    let pixel_bytes = len - len % pixel_size;
+    let mut index = [[0; 4]; 64];
+    let mut c: Channels = [0; 4];
+    let mut at = HEADER_SIZE;
+    let mut next = || -> io::Result<u8> {
+        let byte = *data
+            .get(at)
+            .ok_or_else(|| invalid("truncated QOI stream"))?;
+        at += 1;
+        Ok(byte)
+    };
+    let mut run = 0;
+    for pixel in out[..pixel_bytes].chunks_exact_mut(pixel_size) {
+        if run > 0 {
+            run -= 1;
+            pack(c, pixel);
+            continue;
+        }
+        let op = next()?;
+        match op {
+            OP_RGB => c = [next()?, next()?, next()?, c[3]],
+            OP_RGBA => c = [next()?, next()?, next()?, next()?],
+            _ => match op & MASK_2 {
+                OP_INDEX => c = index[op as usize],
+                OP_DIFF => {
+                    c[0] = c[0].wrapping_add((op >> 4 & 3).wrapping_sub(2));
+                    c[1] = c[1].wrapping_add((op >> 2 & 3).wrapping_sub(2));
+                    c[2] = c[2].wrapping_add((op & 3).wrapping_sub(2));
+                }
+                OP_LUMA => {
+                    let second = next()?;
+                    let dg = (op & 0x3F).wrapping_sub(32);
+                    c[0] = c[0].wrapping_add(dg.wrapping_add(second >> 4).wrapping_sub(8));
+                    c[1] = c[1].wrapping_add(dg);
+                    c[2] = c[2].wrapping_add(dg.wrapping_add(second & 0xF).wrapping_sub(8));
+                }
+                _ => run = op & 0x3F,
+            },
+        }
+        index[hash(c)] = c;
+        pack(c, pixel);
+    }
+    let rest = data.get(at..).unwrap_or_default();
+    if rest.len() != len - pixel_bytes {
+        return Err(invalid("decompressed data has the wrong size"));
+    }
+    out[pixel_bytes..].copy_from_slice(rest);
+    Ok(out)
+}
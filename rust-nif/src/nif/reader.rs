@@ -0,0 +1,1142 @@
+use std::io::{Read, Seek};
+
+use flate2::read::{DeflateDecoder, GzDecoder};
+
+use super::chunks;
+use super::codec::{self, Codec, CODEC_DEFLATE, CODEC_GZIP};
+use super::counting::{self, Counting};
+use super::filter::RowFilter;
+use super::interlace;
+use super::records::{self, IndexEntry};
+use super::rects;
+use super::rle;
+use super::tiles::{self, Tile};
+use super::time::{Rational, Timestamps};
+use super::{
+    frame_size, is_chunked, read_prefix, read_timestamps, resize_frame, ColorSpace, Error, Frame,
+    Header, Metadata, Prefix, Result, FEATURE_FLAGS_DELTA, FEATURE_FLAGS_DIRTY_RECTS,
+    FEATURE_FLAGS_FILTER, FEATURE_FLAGS_FRAME_INDEX, FEATURE_FLAGS_INTERLACE,
+    FEATURE_FLAGS_MIPMAPS, FEATURE_FLAGS_RLE, FEATURE_FLAGS_TILES, FRAME_COUNT_UNKNOWN,
+};
+
+//the frame payload, either stored as is, as a single gzip stream or as
+//one record per frame
+enum Payload<R: Read> {
+    Plain(Counting<R>),
+    Gzip(Box<GzDecoder<Counting<R>>>),
+    Records(Counting<R>),
+}
+
+impl<R: Read> Payload<R> {
+    //offset in the underlying stream, not in the decompressed data
+    fn position(&self) -> u64 {
+        match self {
+            Payload::Plain(buf) => buf.position(),
+            Payload::Gzip(dec) => dec.get_ref().position(),
+            Payload::Records(buf) => buf.position(),
+        }
+    }
+}
+
+impl<R: Read> Read for Payload<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Payload::Plain(inner) => inner.read(buf),
+            Payload::Gzip(dec) => dec.read(buf),
+            Payload::Records(inner) => inner.read(buf),
+        }
+    }
+}
+
+//turns stored frame data back into frames. It holds nothing of the stream,
+//so frame records can be decoded on several threads at once
+struct Decoder {
+    header: Header,
+    features: u32,
+    frame_size: usize,
+    //size of a frame after filtering and interlacing, before compression
+    stored_size: usize,
+    //None if the payload is not compressed
+    codec: Option<&'static dyn Codec>,
+}
+
+//size of an image described by `header` once it is filtered and
+//interlaced, before compression
+fn stored_size(header: &Header, features: u32) -> Result<usize> {
+    let overflow = || Error::DimensionOverflow {
+        offset: 12,
+        width: header.width,
+        height: header.height,
+    };
+    let mut size: usize = 0;
+    for image in images(header, features) {
+        let image_size = match features & FEATURE_FLAGS_FILTER {
+            0 => image.frame_size(),
+            _ => RowFilter::new(&image).filtered_size(),
+        };
+        size = image_size
+            .and_then(|len| size.checked_add(len))
+            .ok_or_else(overflow)?;
+    }
+    Ok(size)
+}
+
+//the images stored for an image described by `header`, the passes when
+//interlaced
+fn images(header: &Header, features: u32) -> Vec<Header> {
+    match features & FEATURE_FLAGS_INTERLACE {
+        0 => vec![*header],
+        _ => interlace::passes(header),
+    }
+}
+
+impl Decoder {
+    fn new(header: Header, features: u32) -> Result<Self> {
+        Ok(Decoder {
+            header,
+            features,
+            frame_size: frame_size(&header)?,
+            stored_size: stored_size(&header, features)?,
+            codec: codec::for_features(features)?,
+        })
+    }
+
+    //the data of a frame record, which must be `len` bytes once decompressed
+    fn decompress(&self, data: &[u8], len: usize, offset: u64) -> Result<Vec<u8>> {
+        let data = match self.codec {
+            Some(codec) => codec.decompress(data, len).map_err(|_| Error::Corrupt {
+                offset,
+                reason: "frame record does not decompress",
+            })?,
+            None => data.to_vec(),
+        };
+        if data.len() != len {
+            return Err(Error::Corrupt {
+                offset,
+                reason: "frame record has the wrong size",
+            });
+        }
+        Ok(data)
+    }
+
+    //the whole rows at the start of `data` of an image described by
+    //`header`, at most all of them, and the bytes they used
+    fn restore_rows(&self, data: &[u8], header: &Header, offset: u64) -> Result<(Vec<u8>, usize)> {
+        let pixel_size = header.pixel_format.get_size();
+        let (width, rows) = (header.width as usize, header.height as usize);
+        let stride = width * pixel_size;
+        if self.features & FEATURE_FLAGS_RLE != 0 {
+            return Ok(rle::decode_rows(data, pixel_size, width, rows));
+        }
+        if self.features & FEATURE_FLAGS_FILTER != 0 {
+            let used = rows.min(data.len() / (stride + 1)) * (stride + 1);
+            let pixels = RowFilter::new(header)
+                .reverse(&data[..used])
+                .ok_or(Error::Corrupt {
+                    offset,
+                    reason: "unknown row filter",
+                })?;
+            return Ok((pixels, used));
+        }
+        let used = match stride {
+            0 => 0,
+            _ => rows.min(data.len() / stride) * stride,
+        };
+        Ok((data[..used].to_vec(), used))
+    }
+
+    //turns the stored data of an image described by `header` back into
+    //pixels, reversing row filters, run-length encoding and interlacing.
+    //With `partial` the data may end early: missing pixels are filled in
+    //from the passes before them when interlaced and are zero otherwise
+    fn restore(&self, data: &[u8], header: &Header, partial: bool, offset: u64) -> Result<Vec<u8>> {
+        let images = images(header, self.features);
+        let mut at = 0;
+        let mut decoded = Vec::with_capacity(images.len());
+        let mut complete = true;
+        for image in &images {
+            let (pixels, used) = self.restore_rows(&data[at..], image, offset)?;
+            at += used;
+            complete = pixels.len()
+                == image.width as usize * image.height as usize * image.pixel_format.get_size();
+            decoded.push(pixels);
+            if !complete {
+                break;
+            }
+        }
+        if !partial && (!complete || at != data.len()) {
+            return Err(Error::Corrupt {
+                offset,
+                reason: match self.features & FEATURE_FLAGS_RLE {
+                    0 => "frame has the wrong size",
+                    _ => "malformed run-length data",
+                },
+            });
+        }
+        if self.features & FEATURE_FLAGS_INTERLACE != 0 {
+            return interlace::merge(header, &decoded, partial);
+        }
+        let mut pixels = decoded.pop().unwrap_or_default();
+        resize_frame(&mut pixels, header)?;
+        Ok(pixels)
+    }
+
+    //run-length decodes or decompresses and unfilters a whole frame, or a
+    //tile or mipmap level described by `header`
+    fn unpack_pixels(&self, data: &[u8], header: &Header, offset: u64) -> Result<Vec<u8>> {
+        if self.features & FEATURE_FLAGS_RLE != 0 {
+            return self.restore(data, header, false, offset);
+        }
+        let data = self.decompress(data, stored_size(header, self.features)?, offset)?;
+        self.restore(&data, header, false, offset)
+    }
+
+    //as much of a stored frame as the start of its compressed data holds,
+    //for codecs that can decompress streams that end early. Nothing for the
+    //other codecs
+    fn decompress_available(&self, data: &[u8]) -> Vec<u8> {
+        match self.codec.map(|codec| codec.id()) {
+            None => data.to_vec(),
+            Some(CODEC_GZIP) => read_available(GzDecoder::new(data), self.stored_size),
+            Some(CODEC_DEFLATE) => read_available(DeflateDecoder::new(data), self.stored_size),
+            Some(_) => Vec::new(),
+        }
+    }
+
+    //the stored tiles of a tiled frame record, row by row
+    fn split_tiles<'a>(&self, data: &'a [u8], offset: u64) -> Result<Vec<&'a [u8]>> {
+        let corrupt = Error::Corrupt {
+            offset,
+            reason: "malformed tile table",
+        };
+        let (tiles_x, tiles_y) = tiles::tile_counts(&self.header);
+        let table_len = tiles_x as usize * tiles_y as usize * tiles::TABLE_ENTRY_SIZE;
+        let Some(table) = data.get(..table_len) else {
+            return Err(corrupt);
+        };
+        let mut at = table_len;
+        let mut out = Vec::with_capacity(table_len / tiles::TABLE_ENTRY_SIZE);
+        for entry in table.chunks_exact(tiles::TABLE_ENTRY_SIZE) {
+            let len = u32::from_be_bytes(entry.try_into().unwrap()) as usize;
+            let Some(tile) = at.checked_add(len).and_then(|end| data.get(at..end)) else {
+                return Err(corrupt);
+            };
+            out.push(tile);
+            at += len;
+        }
+        if at != data.len() {
+            return Err(corrupt);
+        }
+        Ok(out)
+    }
+
+    fn decode_tiles(&self, data: &[u8], offset: u64) -> Result<Vec<u8>> {
+        let stored = self.split_tiles(data, offset)?;
+        let (tiles_x, _) = tiles::tile_counts(&self.header);
+        let decoded = tiles::map_tiles(&self.header, |tx, ty| {
+            let tile_header = tiles::tile_header(&self.header, tx, ty);
+            let i = (ty * tiles_x + tx) as usize;
+            self.unpack_pixels(stored[i], &tile_header, offset)
+        })?;
+        let mut frame = vec![0; self.frame_size];
+        for (i, tile) in decoded.iter().enumerate() {
+            let (tx, ty) = (i as u32 % tiles_x, i as u32 / tiles_x);
+            tiles::insert(&self.header, &mut frame, tx, ty, tile);
+        }
+        Ok(frame)
+    }
+
+    //decodes everything of a record that does not depend on the frame
+    //before it: the frame, the XOR of a delta frame or the rectangle data
+    fn unpack(&self, kind: u8, data: &[u8], offset: u64) -> Result<Vec<u8>> {
+        if kind == records::RECORD_RECTS {
+            //rectangle records start with the size of the rectangle data
+            let corrupt = Error::Corrupt {
+                offset,
+                reason: "malformed dirty rectangles",
+            };
+            let Some(len) = data.get(0..4) else {
+                return Err(corrupt);
+            };
+            let len = u32::from_be_bytes(len.try_into().unwrap()) as usize;
+            //writers only use rectangles when they are smaller than the frame
+            if len > self.frame_size {
+                return Err(corrupt);
+            }
+            return self.decompress(&data[4..], len, offset);
+        }
+        if self.features & FEATURE_FLAGS_TILES != 0 {
+            return self.decode_tiles(data, offset);
+        }
+        self.unpack_pixels(data, &self.header, offset)
+    }
+}
+
+/// Reads a NIF stream one frame at a time.
+///
+/// Only the header and the frame currently being decoded are held in
+/// memory, so arbitrarily long videos can be processed. Unbuffered readers
+/// should be wrapped in a `BufReader`.
+pub struct NifReader<R: Read> {
+    version: u32,
+    features: u32,
+    decoder: Decoder,
+    //the last frame read when frames may depend on the frame before them
+    previous: Option<Vec<u8>>,
+    //the mipmap levels read after the first frame
+    levels: Vec<Frame>,
+    metadata: Metadata,
+    color_space: ColorSpace,
+    icc_profile: Option<Vec<u8>>,
+    exif: Option<Vec<u8>>,
+    xmp: Option<Vec<u8>>,
+    timestamps: Option<Timestamps>,
+    //the chunks after the DATA chunk were read for a `time` chunk
+    trailing_read: bool,
+    //a version 0.1 frame count of 0, which older writers stored for an
+    //image of one frame. Cleared once the payload was checked for that frame
+    legacy_count: bool,
+    //offset of the first frame
+    payload_start: u64,
+    next_frame: u32,
+    //the frame index of a record payload, loaded on the first seek
+    index: Option<Vec<IndexEntry>>,
+    //set once the end of the payload was checked or an error was returned
+    done: bool,
+    payload: Payload<R>,
+}
+
+impl<R: Read> NifReader<R> {
+    //Parses the magic number, version, feature flags and header.
+    pub fn new(reader: R) -> Result<Self> {
+        let mut buf = Counting::new(reader);
+        let prefix = read_prefix(&mut buf)?;
+        let mut reader = Self::with_payload(buf, prefix.version, prefix.features, prefix.header)?;
+        reader.metadata = prefix.metadata;
+        reader.color_space = prefix.color_space;
+        reader.icc_profile = prefix.icc_profile;
+        reader.exif = prefix.exif;
+        reader.xmp = prefix.xmp;
+        reader.timestamps = prefix.timestamps;
+        Ok(reader)
+    }
+
+    //`buf` must be positioned right after the header
+    pub(crate) fn with_payload(
+        buf: Counting<R>,
+        version: u32,
+        features: u32,
+        header: Header,
+    ) -> Result<Self> {
+        let decoder = Decoder::new(header, features)?;
+        let payload_start = buf.position();
+        let payload = if features & FEATURE_FLAGS_FRAME_INDEX != 0 {
+            Payload::Records(buf)
+        } else if decoder.codec.is_some() {
+            Payload::Gzip(Box::new(GzDecoder::new(buf)))
+        } else {
+            Payload::Plain(buf)
+        };
+        Ok(NifReader {
+            version,
+            features,
+            decoder,
+            previous: None,
+            levels: Vec::new(),
+            metadata: Metadata::new(),
+            color_space: ColorSpace::SRGB,
+            icc_profile: None,
+            exif: None,
+            xmp: None,
+            timestamps: None,
+            trailing_read: false,
+            legacy_count: !is_chunked(version)
+                && header.frame_count == 0
+                && features & FEATURE_FLAGS_FRAME_INDEX == 0,
+            payload_start,
+            next_frame: 0,
+            index: None,
+            done: false,
+            payload,
+        })
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+    pub fn features(&self) -> u32 {
+        self.features
+    }
+    pub fn header(&self) -> &Header {
+        &self.decoder.header
+    }
+    //None if the stream was written without knowing its length
+    pub fn frame_count(&self) -> Option<u32> {
+        if self.decoder.header.frame_count == FRAME_COUNT_UNKNOWN {
+            None
+        } else {
+            Some(self.decoder.header.frame_count)
+        }
+    }
+    //Mipmap levels 1 and up of the first frame with FEATURE_FLAGS_MIPMAPS,
+    //empty until the reader got past them.
+    pub fn levels(&self) -> &[Frame] {
+        &self.levels
+    }
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+    pub fn icc_profile(&self) -> Option<&[u8]> {
+        self.icc_profile.as_deref()
+    }
+    pub fn exif(&self) -> Option<&[u8]> {
+        self.exif.as_deref()
+    }
+    pub fn xmp(&self) -> Option<&[u8]> {
+        self.xmp.as_deref()
+    }
+    //The timebase of the frame timestamps, None if the stream has none.
+    //Streams written frame by frame store their timestamps after the frames,
+    //those are only known once all frames were read or after
+    //read_frame_at_time.
+    pub fn timebase(&self) -> Option<Rational> {
+        self.timestamps.as_ref().map(|t| t.timebase)
+    }
+    //Index of the frame shown `seconds` after the start, the first frame for
+    //earlier times. Streams without timestamps use the frame rate of the
+    //header, None if they have none either.
+    pub fn frame_index_at(&self, seconds: f64) -> Option<u32> {
+        if let Some(timestamps) = &self.timestamps {
+            return timestamps.frame_at(seconds);
+        }
+        let frame_rate = self.decoder.header.rate.to_f64();
+        if !(frame_rate > 0.0 && frame_rate.is_finite()) {
+            return None;
+        }
+        let index = (seconds * frame_rate).floor().clamp(0.0, u32::MAX as f64) as u32;
+        match self.frame_count() {
+            Some(0) => None,
+            Some(count) => Some(index.min(count - 1)),
+            None => Some(index),
+        }
+    }
+    pub(crate) fn take_levels(&mut self) -> Vec<Frame> {
+        std::mem::take(&mut self.levels)
+    }
+    //index of the frame the next call to next_frame returns
+    pub fn frame_index(&self) -> u32 {
+        self.next_frame
+    }
+
+    //Returns the next frame, or None once all frames have been read.
+    pub fn next_frame(&mut self) -> Result<Option<Frame>> {
+        if self.done {
+            return Ok(None);
+        }
+        let mut result = self.read_next();
+        if let Ok(None) = result {
+            result = self.finish_payload().map(|()| None);
+        }
+        if !matches!(result, Ok(Some(_))) {
+            self.done = true;
+        }
+        result
+    }
+
+    //Reads all frames that are left. With the `parallel` feature frame
+    //records are decoded on the rayon thread pool.
+    pub fn read_remaining(&mut self) -> Result<Vec<Frame>> {
+        let first = self.next_frame;
+        let mut frames = self.read_all()?;
+        //timestamps stored after the frames are only known now
+        if let Some(timestamps) = &self.timestamps {
+            for (i, frame) in frames.iter_mut().enumerate() {
+                frame.timestamp = timestamps.get(first + i as u32);
+            }
+        }
+        Ok(frames)
+    }
+
+    fn read_all(&mut self) -> Result<Vec<Frame>> {
+        let mut frames = Vec::new();
+        #[cfg(feature = "parallel")]
+        if let Payload::Records(_) = self.payload {
+            if !self.done {
+                let result = self.read_parallel(&mut frames);
+                self.done = true;
+                result?;
+            }
+            return Ok(frames);
+        }
+        while let Some(frame) = self.next_frame()? {
+            frames.push(frame);
+        }
+        Ok(frames)
+    }
+
+    //reads a few records per thread at a time and decodes them together.
+    //Errors are returned in the same order as by next_frame
+    #[cfg(feature = "parallel")]
+    fn read_parallel(&mut self, frames: &mut Vec<Frame>) -> Result<()> {
+        use rayon::prelude::*;
+        let batch_size = rayon::current_num_threads() * 2;
+        loop {
+            let mut batch = Vec::with_capacity(batch_size);
+            let status = loop {
+                if batch.len() == batch_size {
+                    break Ok(false);
+                }
+                match self.next_record(self.next_frame + batch.len() as u32) {
+                    Ok(Some(record)) => batch.push(record),
+                    Ok(None) => break Ok(true),
+                    Err(e) => break Err(e),
+                }
+            };
+            let decoder = &self.decoder;
+            let unpacked: Vec<_> = batch
+                .par_iter()
+                .map(|(kind, data, offset)| decoder.unpack(*kind, data, *offset))
+                .collect();
+            for ((kind, _, offset), data) in batch.iter().zip(unpacked) {
+                frames.push(self.apply_record(*kind, data?, *offset)?);
+            }
+            if status? {
+                return self.finish_payload();
+            }
+        }
+    }
+
+    fn read_next(&mut self) -> Result<Option<Frame>> {
+        if let Payload::Records(_) = self.payload {
+            return self.read_record();
+        }
+        if self.frame_count().is_none() {
+            //read until the stream ends on a frame boundary
+            let position = self.payload.position();
+            let data = counting::read_up_to(&mut self.payload, self.decoder.stored_size)
+                .map_err(|e| Error::from_io(e, position))?;
+            if data.is_empty() {
+                return Ok(None);
+            }
+            if data.len() < self.decoder.stored_size {
+                return Err(Error::Truncated {
+                    offset: self.payload.position(),
+                });
+            }
+            let header = self.decoder.header;
+            let data = self.decoder.restore(&data, &header, false, position)?;
+            return Ok(Some(self.take_frame(data)));
+        }
+        if self.legacy_count {
+            self.legacy_count = false;
+            let position = self.payload.position();
+            let data = counting::read_up_to(&mut self.payload, self.decoder.stored_size)
+                .map_err(|e| Error::from_io(e, position))?;
+            if data.is_empty() {
+                return Ok(None);
+            }
+            if data.len() < self.decoder.stored_size {
+                return Err(Error::Truncated {
+                    offset: self.payload.position(),
+                });
+            }
+            //an image after all, anything after its frame is trailing data
+            self.decoder.header.frame_count = 1;
+            let header = self.decoder.header;
+            let data = self.decoder.restore(&data, &header, false, position)?;
+            return Ok(Some(self.take_frame(data)));
+        }
+        if self.next_frame >= self.decoder.header.frame_count {
+            //anything after the last frame means the header and payload disagree
+            let position = self.payload.position();
+            if !counting::at_eof(&mut self.payload).map_err(|e| Error::from_io(e, position))? {
+                return Err(Error::TrailingData { offset: position });
+            }
+            return Ok(None);
+        }
+        let position = self.payload.position();
+        let data = counting::read_vec(&mut self.payload, self.decoder.stored_size)
+            .map_err(|e| Error::from_io(e, self.payload.position()))?;
+        let header = self.decoder.header;
+        let data = self.decoder.restore(&data, &header, false, position)?;
+        Ok(Some(self.take_frame(data)))
+    }
+
+    //the frame read as frame number next_frame, with its timestamp
+    fn take_frame(&mut self, data: Vec<u8>) -> Frame {
+        let mut frame = Frame::from(data);
+        frame.timestamp = self
+            .timestamps
+            .as_ref()
+            .and_then(|t| t.get(self.next_frame));
+        self.next_frame += 1;
+        frame
+    }
+
+    //checks the chunks after the DATA chunk of a version 0.2 stream once
+    //all frames were read
+    fn finish_payload(&mut self) -> Result<()> {
+        if !is_chunked(self.version) {
+            return Ok(());
+        }
+        let buf = match &mut self.payload {
+            Payload::Plain(buf) | Payload::Records(buf) => buf,
+            Payload::Gzip(dec) => dec.get_mut(),
+        };
+        //without a length the DATA chunk is the last one
+        let Some(end) = buf.end() else {
+            return Ok(());
+        };
+        //whatever is left after a gzip stream
+        chunks::skip_chunk_data(buf, end - buf.position())?;
+        buf.set_end(None);
+        let leading = self.timestamps.is_some() && !self.trailing_read;
+        let result = read_trailing_timestamps(buf, self.next_frame, leading);
+        buf.set_end(Some(end));
+        if let Some(timestamps) = result? {
+            self.timestamps = Some(timestamps);
+        }
+        self.trailing_read = true;
+        Ok(())
+    }
+
+    fn read_record(&mut self) -> Result<Option<Frame>> {
+        let Some((kind, data, offset)) = self.next_record(self.next_frame)? else {
+            return Ok(None);
+        };
+        let data = self.decoder.unpack(kind, &data, offset)?;
+        self.apply_record(kind, data, offset).map(Some)
+    }
+
+    //reads the record of frame number `index` and returns its type, data
+    //and offset, or None after checking the end of the records
+    fn next_record(&mut self, index: u32) -> Result<Option<(u8, Vec<u8>, u64)>> {
+        let frame_count = self.frame_count();
+        let buf = match &mut self.payload {
+            Payload::Records(buf) => buf,
+            _ => unreachable!(),
+        };
+        let mipmaps = self.features & FEATURE_FLAGS_MIPMAPS != 0;
+        let (offset, kind, length) = loop {
+            let offset = buf.position();
+            let (kind, length) = records::read_record_header(buf)?;
+            if !(records::RECORD_ANCILLARY..records::RECORD_END).contains(&kind) {
+                break (offset, kind, length);
+            }
+            let data = records::read_record_data(buf, length)?;
+            if kind == records::RECORD_LEVEL && mipmaps {
+                //levels 1 and up follow the first frame, in order
+                let n = self.levels.len() as u32 + 1;
+                if index != 1 || n >= self.decoder.header.level_count() {
+                    return Err(Error::Corrupt {
+                        offset,
+                        reason: "misplaced mipmap level",
+                    });
+                }
+                let level_header = self.decoder.header.level(n);
+                let level = self.decoder.unpack_pixels(&data, &level_header, offset)?;
+                self.levels.push(Frame::from(level));
+            }
+        };
+        let delta = self.features & FEATURE_FLAGS_DELTA != 0;
+        let dirty_rects = self.features & FEATURE_FLAGS_DIRTY_RECTS != 0;
+        match kind {
+            records::RECORD_FRAME => {}
+            records::RECORD_DELTA if delta => {}
+            records::RECORD_RECTS if dirty_rects => {}
+            records::RECORD_END => {
+                if frame_count.is_some_and(|count| count != index) {
+                    return Err(Error::FrameCountMismatch {
+                        offset,
+                        expected: self.decoder.header.frame_count,
+                        found: index as usize,
+                    });
+                }
+                if mipmaps
+                    && index == 1
+                    && self.levels.len() as u32 + 1 != self.decoder.header.level_count()
+                {
+                    return Err(Error::Corrupt {
+                        offset,
+                        reason: "missing mipmap levels",
+                    });
+                }
+                //the index and footer must be all that is left
+                let index_len = index as u64 * records::INDEX_ENTRY_SIZE;
+                let Ok(rest_len) = u32::try_from(index_len + records::FOOTER_SIZE) else {
+                    return Err(Error::Corrupt {
+                        offset,
+                        reason: "frame index larger than 4 GiB",
+                    });
+                };
+                let rest = records::read_record_data(buf, rest_len)?;
+                let footer_offset = buf.position() - records::FOOTER_SIZE;
+                let (index_offset, count) =
+                    records::parse_footer(&rest[index_len as usize..], footer_offset)?;
+                let expected_offset = offset + records::RECORD_HEADER_SIZE - self.payload_start;
+                if count != index || index_offset != expected_offset {
+                    return Err(Error::Corrupt {
+                        offset: footer_offset,
+                        reason: "frame index does not match the frame records",
+                    });
+                }
+                let position = buf.position();
+                if !counting::at_eof(buf).map_err(|e| Error::from_io(e, position))? {
+                    return Err(Error::TrailingData { offset: position });
+                }
+                return Ok(None);
+            }
+            _ => {
+                return Err(Error::Corrupt {
+                    offset,
+                    reason: "unknown record type",
+                })
+            }
+        }
+        if frame_count.is_some_and(|count| index >= count) {
+            return Err(Error::FrameCountMismatch {
+                offset,
+                expected: self.decoder.header.frame_count,
+                found: index as usize + 1,
+            });
+        }
+        let data = records::read_record_data(buf, length)?;
+        if index == 0 {
+            self.levels.clear();
+        }
+        Ok(Some((kind, data, offset)))
+    }
+
+    //turns the unpacked data of the next record into its frame
+    fn apply_record(&mut self, kind: u8, data: Vec<u8>, offset: u64) -> Result<Frame> {
+        let data = match kind {
+            records::RECORD_DELTA => records::xor(&data, self.previous(offset)?),
+            records::RECORD_RECTS => {
+                let mut frame = self.previous(offset)?.to_vec();
+                rects::apply(&self.decoder.header, &data, &mut frame).ok_or(Error::Corrupt {
+                    offset,
+                    reason: "malformed dirty rectangles",
+                })?;
+                frame
+            }
+            _ => data,
+        };
+        if self.features & (FEATURE_FLAGS_DELTA | FEATURE_FLAGS_DIRTY_RECTS) != 0 {
+            self.previous = Some(data.clone());
+        }
+        Ok(self.take_frame(data))
+    }
+
+    //the frame the current delta or rectangle record is based on
+    fn previous(&self, offset: u64) -> Result<&[u8]> {
+        self.previous.as_deref().ok_or(Error::Corrupt {
+            offset,
+            reason: "delta frame without a keyframe before it",
+        })
+    }
+}
+
+impl<R: Read + Seek> NifReader<R> {
+    //reads a `time` chunk after the DATA chunk without reading the frames,
+    //which keeps the position in the stream
+    fn load_trailing_timestamps(&mut self) -> Result<()> {
+        if self.trailing_read || self.timestamps.is_some() || !is_chunked(self.version) {
+            return Ok(());
+        }
+        let buf = match &mut self.payload {
+            Payload::Plain(buf) | Payload::Records(buf) => buf,
+            Payload::Gzip(_) => return Ok(()),
+        };
+        //without a length the DATA chunk is the last one
+        let Some(end) = buf.end() else {
+            return Ok(());
+        };
+        let position = buf.position();
+        buf.seek_to(end)?;
+        buf.set_end(None);
+        let result = read_trailing_timestamps(buf, self.decoder.header.frame_count, false);
+        buf.set_end(Some(end));
+        buf.seek_to(position)?;
+        self.timestamps = result?;
+        self.trailing_read = true;
+        Ok(())
+    }
+
+    //Positions the reader so that the next call to next_frame returns frame
+    //`index`. Uncompressed streams, whose frames have a fixed size, and
+    //streams with a frame index can seek.
+    pub fn seek_to_frame(&mut self, index: u32) -> Result<()> {
+        if let Payload::Records(_) = self.payload {
+            return self.seek_to_record(index);
+        }
+        let position = self.payload.position();
+        let count = match self.legacy_count {
+            true => 1,
+            false => self.frame_count().unwrap_or(FRAME_COUNT_UNKNOWN),
+        };
+        if index >= count {
+            return Err(Error::FrameOutOfRange {
+                offset: position,
+                index,
+                count: self.decoder.header.frame_count,
+            });
+        }
+        let buf = match &mut self.payload {
+            Payload::Plain(buf) => buf,
+            _ => {
+                return Err(Error::Unsupported {
+                    offset: position,
+                    reason: "seeking in a compressed stream",
+                })
+            }
+        };
+        //a corrupt header can put the frame past anything a stream can hold
+        let start = (index as u64)
+            .checked_mul(self.decoder.frame_size as u64)
+            .and_then(|offset| offset.checked_add(self.payload_start))
+            .filter(|&start| start <= i64::MAX as u64)
+            .ok_or(Error::FrameOutOfRange {
+                offset: position,
+                index,
+                count: self.decoder.header.frame_count,
+            })?;
+        buf.seek_to(start)?;
+        self.next_frame = index;
+        self.done = false;
+        Ok(())
+    }
+
+    //loads the frame index of a record payload if it was not loaded yet,
+    //which moves the stream
+    fn load_index(&mut self) -> Result<()> {
+        let buf = match &mut self.payload {
+            Payload::Records(buf) => buf,
+            _ => unreachable!(),
+        };
+        if self.index.is_none() {
+            //the footer is at the very end of the stream
+            buf.seek_from_end(-(records::FOOTER_SIZE as i64))?;
+            let footer_offset = buf.position();
+            let footer = records::read_record_data(buf, records::FOOTER_SIZE as u32)?;
+            let (index_offset, count) = records::parse_footer(&footer, footer_offset)?;
+            //the index must end right at the footer and every record must end
+            //before the index
+            let corrupt = Error::Corrupt {
+                offset: footer_offset,
+                reason: "malformed frame index",
+            };
+            let Ok(index_len) = u32::try_from(count as u64 * records::INDEX_ENTRY_SIZE) else {
+                return Err(corrupt);
+            };
+            let index_start = self.payload_start.checked_add(index_offset);
+            if index_start.and_then(|start| start.checked_add(index_len as u64))
+                != Some(footer_offset)
+            {
+                return Err(corrupt);
+            }
+            buf.seek_to(self.payload_start + index_offset)?;
+            let index = records::read_record_data(buf, index_len)?;
+            let entries = records::parse_index(&index);
+            let outside = |entry: &records::IndexEntry| {
+                entry
+                    .offset
+                    .checked_add(records::RECORD_HEADER_SIZE + entry.length as u64)
+                    .is_none_or(|end| end > index_offset)
+            };
+            if entries.iter().any(outside) {
+                return Err(corrupt);
+            }
+            self.index = Some(entries);
+        }
+        Ok(())
+    }
+
+    fn seek_to_record(&mut self, index: u32) -> Result<()> {
+        self.load_index()?;
+        let buf = match &mut self.payload {
+            Payload::Records(buf) => buf,
+            _ => unreachable!(),
+        };
+        let entries = self.index.as_ref().unwrap();
+        if index as usize >= entries.len() {
+            return Err(Error::FrameOutOfRange {
+                offset: buf.position(),
+                index,
+                count: entries.len() as u32,
+            });
+        }
+        //deltas are decoded starting from the keyframe before them
+        let keyframe = entries[..=index as usize]
+            .iter()
+            .rposition(|entry| entry.kind == records::RECORD_FRAME)
+            .ok_or(Error::Corrupt {
+                offset: buf.position(),
+                reason: "delta frame without a keyframe before it",
+            })?;
+        buf.seek_to(self.payload_start + entries[keyframe].offset)?;
+        self.next_frame = keyframe as u32;
+        self.previous = None;
+        self.done = false;
+        while self.next_frame < index {
+            if self.read_record()?.is_none() {
+                return Err(Error::Corrupt {
+                    offset: self.payload.position(),
+                    reason: "frame index does not match the frame records",
+                });
+            }
+        }
+        Ok(())
+    }
+
+    //Reads tile (`tx`, `ty`) of frame `index` of a tiled stream without
+    //decoding the rest of the frame. The reader keeps its position.
+    pub fn read_tile(&mut self, index: u32, tx: u32, ty: u32) -> Result<Tile> {
+        let position = self.payload.position();
+        if self.features & FEATURE_FLAGS_TILES == 0 {
+            return Err(Error::Unsupported {
+                offset: position,
+                reason: "the stream is not tiled",
+            });
+        }
+        let header = self.decoder.header;
+        let (tiles_x, tiles_y) = tiles::tile_counts(&header);
+        if tx >= tiles_x || ty >= tiles_y {
+            return Err(Error::TileOutOfRange {
+                offset: position,
+                tx,
+                ty,
+                tiles_x,
+                tiles_y,
+            });
+        }
+        let result = self.read_stored_tile(index, ty * tiles_x + tx);
+        let buf = match &mut self.payload {
+            Payload::Records(buf) => buf,
+            _ => unreachable!(),
+        };
+        buf.seek_to(position)?;
+        let (data, offset) = result?;
+        let tile_header = tiles::tile_header(&header, tx, ty);
+        Ok(Tile {
+            x: tx * tiles::TILE_SIZE,
+            y: ty * tiles::TILE_SIZE,
+            width: tile_header.width,
+            height: tile_header.height,
+            data: self.decoder.unpack_pixels(&data, &tile_header, offset)?,
+        })
+    }
+
+    //reads the table of a tiled frame record and then tile number `tile`
+    //only, returns the tile and its offset
+    fn read_stored_tile(&mut self, index: u32, tile: u32) -> Result<(Vec<u8>, u64)> {
+        self.load_index()?;
+        let buf = match &mut self.payload {
+            Payload::Records(buf) => buf,
+            _ => unreachable!(),
+        };
+        let entries = self.index.as_ref().unwrap();
+        let Some(entry) = entries.get(index as usize) else {
+            return Err(Error::FrameOutOfRange {
+                offset: buf.position(),
+                index,
+                count: entries.len() as u32,
+            });
+        };
+        let record = self.payload_start + entry.offset + records::RECORD_HEADER_SIZE;
+        let (tiles_x, tiles_y) = tiles::tile_counts(&self.decoder.header);
+        let table_len = tiles_x as u64 * tiles_y as u64 * tiles::TABLE_ENTRY_SIZE as u64;
+        let corrupt = Error::Corrupt {
+            offset: record,
+            reason: "malformed tile table",
+        };
+        if entry.kind != records::RECORD_FRAME || table_len > entry.length as u64 {
+            return Err(corrupt);
+        }
+        buf.seek_to(record)?;
+        let table = records::read_record_data(buf, table_len as u32)?;
+        let mut lengths = table
+            .chunks_exact(tiles::TABLE_ENTRY_SIZE)
+            .map(|entry| u32::from_be_bytes(entry.try_into().unwrap()) as u64);
+        let start = table_len + lengths.by_ref().take(tile as usize).sum::<u64>();
+        let len = lengths.next().unwrap();
+        if start + len > entry.length as u64 {
+            return Err(corrupt);
+        }
+        buf.seek_to(record + start)?;
+        Ok((records::read_record_data(buf, len as u32)?, record + start))
+    }
+
+    //Reads frame `index` without decoding the frames before it.
+    pub fn read_frame_at(&mut self, index: u32) -> Result<Frame> {
+        self.seek_to_frame(index)?;
+        let position = self.payload.position();
+        self.next_frame()?.ok_or(Error::FrameOutOfRange {
+            offset: position,
+            index,
+            count: self.decoder.header.frame_count,
+        })
+    }
+
+    //Reads the frame shown `seconds` after the start, see frame_index_at.
+    pub fn read_frame_at_time(&mut self, seconds: f64) -> Result<Frame> {
+        self.load_trailing_timestamps()?;
+        let index = self.frame_index_at(seconds).ok_or(Error::Unsupported {
+            offset: self.payload.position(),
+            reason: "the stream has neither timestamps nor a frame rate",
+        })?;
+        self.read_frame_at(index)
+    }
+}
+
+//checks the chunks after the DATA chunk and returns the timestamps of a
+//`time` chunk among them. There cannot be one if there was one before the
+//DATA chunk
+fn read_trailing_timestamps<R: Read>(
+    buf: &mut Counting<R>,
+    frame_count: u32,
+    leading: bool,
+) -> Result<Option<Timestamps>> {
+    let mut timestamps = None;
+    chunks::read_trailing_chunks(buf, |buf, tag, length, offset| {
+        if tag != chunks::CHUNK_TIME {
+            return Ok(false);
+        }
+        if leading || timestamps.is_some() {
+            return Err(Error::Corrupt {
+                offset,
+                reason: "more than one time chunk",
+            });
+        }
+        timestamps = Some(read_timestamps(buf, length, offset, frame_count)?);
+        Ok(true)
+    })?;
+    Ok(timestamps)
+}
+
+/// The best picture of the first frame of a stream that its start holds.
+///
+/// Interlaced streams give a coarse picture of the whole frame that gets
+/// sharper with every pass, other streams the rows at the top of the frame
+/// and zeros below them.
+pub struct Preview {
+    pub header: Header,
+    pub frame: Frame,
+    //false until the whole frame was decoded
+    pub complete: bool,
+}
+
+//Decodes as much of the first frame as the start of a stream holds, to show
+//a preview of a file that is still being downloaded. The header must be
+//complete. Uncompressed frames and frames compressed with gzip or deflate
+//are decoded as far as they go, frames compressed with other codecs and
+//tiled frames only once their record is complete.
+pub fn decode_partial(bytes: &[u8]) -> Result<Preview> {
+    let mut buf = Counting::new(bytes);
+    let Prefix {
+        features, header, ..
+    } = read_prefix(&mut buf)?;
+    let decoder = Decoder::new(header, features)?;
+    let offset = buf.position();
+    let end = buf.end().unwrap_or(u64::MAX).min(bytes.len() as u64);
+    let payload = &bytes[offset as usize..end as usize];
+    if header.frame_count == 0 {
+        return Err(Error::FrameOutOfRange {
+            offset,
+            index: 0,
+            count: 0,
+        });
+    }
+    let stored = if features & FEATURE_FLAGS_FRAME_INDEX != 0 {
+        let Some((start, length)) = first_record(payload, offset)? else {
+            let mut frame = Vec::new();
+            resize_frame(&mut frame, &header)?;
+            return Ok(Preview {
+                header,
+                frame: Frame::from(frame),
+                complete: false,
+            });
+        };
+        let data = &payload[start..payload.len().min(start + length)];
+        let offset = offset + start as u64;
+        if data.len() == length {
+            let frame = decoder.unpack(records::RECORD_FRAME, data, offset)?;
+            return Ok(Preview {
+                header,
+                frame: Frame::from(frame),
+                complete: true,
+            });
+        }
+        match features & FEATURE_FLAGS_TILES {
+            0 => decoder.decompress_available(data),
+            _ => Vec::new(),
+        }
+    } else {
+        decoder.decompress_available(payload)
+    };
+    let complete = features & FEATURE_FLAGS_RLE == 0 && stored.len() >= decoder.stored_size;
+    let stored = &stored[..stored.len().min(decoder.stored_size)];
+    let frame = decoder.restore(stored, &header, !complete, offset)?;
+    Ok(Preview {
+        header,
+        frame: Frame::from(frame),
+        complete,
+    })
+}
+
+//the start of the data of the first frame record in a record payload and
+//its length. None if the record header is not there yet
+fn first_record(payload: &[u8], offset: u64) -> Result<Option<(usize, usize)>> {
+    let header_size = records::RECORD_HEADER_SIZE as usize;
+    let mut at = 0;
+    loop {
+        let Some(record_header) = payload.get(at..at + header_size) else {
+            return Ok(None);
+        };
+        let kind = record_header[0];
+        let length = u32::from_be_bytes(record_header[1..].try_into().unwrap()) as usize;
+        let start = at + header_size;
+        let offset = offset + at as u64;
+        match kind {
+            records::RECORD_FRAME => return Ok(Some((start, length))),
+            records::RECORD_END => {
+                return Err(Error::FrameOutOfRange {
+                    offset,
+                    index: 0,
+                    count: 0,
+                })
+            }
+            //ancillary records are skipped like by NifReader
+            records::RECORD_ANCILLARY.. => at = start + length,
+            records::RECORD_DELTA | records::RECORD_RECTS => {
+                return Err(Error::Corrupt {
+                    offset,
+                    reason: "delta frame without a keyframe before it",
+                })
+            }
+            _ => {
+                return Err(Error::Corrupt {
+                    offset,
+                    reason: "unknown record type",
+                })
+            }
+        }
+    }
+}
+
+//reads from `reader` until it ends, fails or gave `limit` bytes
+fn read_available(reader: impl Read, limit: usize) -> Vec<u8> {
+    let mut out = Vec::new();
+    //an error only means the data ends early, what was read is kept
+    let _ = reader.take(limit as u64).read_to_end(&mut out);
+    out
+}
+
+impl<R: Read> Iterator for NifReader<R> {
+    type Item = Result<Frame>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
@@ -0,0 +1,112 @@
+//Frame records, the payload layout used when FEATURE_FLAGS_FRAME_INDEX is set.
+//
+//Every frame is stored as its own record: a one byte record type, the length
+//of the record data as a u32 and the data itself, compressed on its own with
+//the codec from the feature flags when FEATURE_FLAGS_COMPRESSION is set. A
+//record of type RECORD_END with no data closes the list and is followed by
+//the index: one entry per frame holding the offset of its record (u64), the
+//length of its data (u32) and its type (u8). A footer with the offset of the index (u64),
+//the number of entries (u32) and the tag "NIFX" ends the payload. Offsets
+//are relative to the start of the payload.
+use std::io::{Read, Write};
+
+use super::counting::{self, Counting};
+use super::{Error, Result};
+
+pub(crate) const RECORD_FRAME: u8 = 0;
+//the XOR of the frame and the frame before it, with FEATURE_FLAGS_DELTA
+pub(crate) const RECORD_DELTA: u8 = 1;
+//the parts of the frame that changed, with FEATURE_FLAGS_DIRTY_RECTS
+pub(crate) const RECORD_RECTS: u8 = 2;
+//record types from here up to RECORD_END are ancillary, readers skip the
+//ones they do not know. They are not part of the index
+pub(crate) const RECORD_ANCILLARY: u8 = 0x80;
+//a mipmap level of the frame before it, with FEATURE_FLAGS_MIPMAPS
+pub(crate) const RECORD_LEVEL: u8 = 0x80;
+pub(crate) const RECORD_END: u8 = 0xFF;
+pub(crate) const RECORD_HEADER_SIZE: u64 = 5;
+pub(crate) const INDEX_ENTRY_SIZE: u64 = 13;
+pub(crate) const FOOTER_SIZE: u64 = 16;
+const INDEX_TAG: [u8; 4] = *b"NIFX";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct IndexEntry {
+    pub(crate) offset: u64,
+    pub(crate) length: u32,
+    pub(crate) kind: u8,
+}
+
+//the delta between two frames of the same size, also undoes a delta
+pub(crate) fn xor(frame: &[u8], previous: &[u8]) -> Vec<u8> {
+    frame.iter().zip(previous).map(|(a, b)| a ^ b).collect()
+}
+
+pub(crate) fn write_record<W: Write>(buf: &mut Counting<W>, kind: u8, data: &[u8]) -> Result<()> {
+    let offset = buf.position();
+    let length = u32::try_from(data.len()).map_err(|_| Error::Corrupt {
+        offset,
+        reason: "record larger than 4 GiB",
+    })?;
+    let mut record_header = [0; RECORD_HEADER_SIZE as usize];
+    record_header[0] = kind;
+    record_header[1..5].copy_from_slice(&length.to_be_bytes());
+    buf.write_checked(&record_header)?;
+    buf.write_checked(data)
+}
+
+//returns the type and data length of the next record
+pub(crate) fn read_record_header<R: Read>(buf: &mut Counting<R>) -> Result<(u8, u32)> {
+    let mut record_header = [0; RECORD_HEADER_SIZE as usize];
+    buf.read_checked(&mut record_header)?;
+    let length = u32::from_be_bytes(record_header[1..5].try_into().unwrap());
+    Ok((record_header[0], length))
+}
+
+pub(crate) fn read_record_data<R: Read>(buf: &mut Counting<R>, length: u32) -> Result<Vec<u8>> {
+    let position = buf.position();
+    counting::read_vec(buf, length as usize).map_err(|e| Error::from_io(e, position))
+}
+
+//writes the end record, the index and the footer
+pub(crate) fn write_index<W: Write>(
+    buf: &mut Counting<W>,
+    payload_start: u64,
+    entries: &[IndexEntry],
+) -> Result<()> {
+    write_record(buf, RECORD_END, &[])?;
+    let index_offset = buf.position() - payload_start;
+    for entry in entries {
+        buf.write_checked(&entry.offset.to_be_bytes())?;
+        buf.write_checked(&entry.length.to_be_bytes())?;
+        buf.write_checked(&[entry.kind])?;
+    }
+    let mut footer = [0; FOOTER_SIZE as usize];
+    footer[0..8].copy_from_slice(&index_offset.to_be_bytes());
+    footer[8..12].copy_from_slice(&(entries.len() as u32).to_be_bytes());
+    footer[12..16].copy_from_slice(&INDEX_TAG);
+    buf.write_checked(&footer)
+}
+
+//returns the offset of the index and its number of entries
+pub(crate) fn parse_footer(footer: &[u8], offset: u64) -> Result<(u64, u32)> {
+    if footer[12..16] != INDEX_TAG {
+        return Err(Error::Corrupt {
+            offset: offset + 12,
+            reason: "missing frame index footer",
+        });
+    }
+    let index_offset = u64::from_be_bytes(footer[0..8].try_into().unwrap());
+    let count = u32::from_be_bytes(footer[8..12].try_into().unwrap());
+    Ok((index_offset, count))
+}
+
+pub(crate) fn parse_index(index: &[u8]) -> Vec<IndexEntry> {
+    index
+        .chunks_exact(INDEX_ENTRY_SIZE as usize)
+        .map(|entry| IndexEntry {
+            offset: u64::from_be_bytes(entry[0..8].try_into().unwrap()),
+            length: u32::from_be_bytes(entry[8..12].try_into().unwrap()),
+            kind: entry[12],
+        })
+        .collect()
+}
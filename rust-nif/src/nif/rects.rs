@@ -0,0 +1,125 @@
+//Dirty rectangles, the frame records used with FEATURE_FLAGS_DIRTY_RECTS.
+//
+//A rectangle record holds only the parts of a frame that differ from the
+//frame before it: the number of rectangles (u32), then for every rectangle
+//its x, y, width and height (u32 each) followed by its pixels, row by row.
+//Pixels outside the rectangles are the same as in the frame before.
+use super::Header;
+
+//changes are searched for in blocks of this many pixels squared
+const BLOCK_SIZE: u32 = 16;
+const RECT_HEADER_SIZE: usize = 16;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct Rect {
+    pub(crate) x: u32,
+    pub(crate) y: u32,
+    pub(crate) w: u32,
+    pub(crate) h: u32,
+}
+
+//finds rectangles covering every pixel that differs between the frames.
+//Changed blocks are joined into horizontal runs, and runs spanning the same
+//columns in consecutive block rows are joined into one rectangle.
+pub(crate) fn diff(header: &Header, frame: &[u8], previous: &[u8]) -> Vec<Rect> {
+    let bpp = header.pixel_format.get_size();
+    let stride = header.width as usize * bpp;
+    let block_changed = |bx: u32, by: u32| {
+        let x = bx * BLOCK_SIZE;
+        let w = BLOCK_SIZE.min(header.width - x) as usize;
+        let y = by * BLOCK_SIZE;
+        (y..(y + BLOCK_SIZE).min(header.height)).any(|y| {
+            let start = y as usize * stride + x as usize * bpp;
+            frame[start..start + w * bpp] != previous[start..start + w * bpp]
+        })
+    };
+    let blocks_x = header.width.div_ceil(BLOCK_SIZE);
+    let blocks_y = header.height.div_ceil(BLOCK_SIZE);
+    //rectangles still open at the bottom, in blocks
+    let mut open: Vec<Rect> = Vec::new();
+    let mut rects = Vec::new();
+    for by in 0..blocks_y {
+        let mut runs = Vec::new();
+        let mut bx = 0;
+        while bx < blocks_x {
+            if block_changed(bx, by) {
+                let start = bx;
+                while bx < blocks_x && block_changed(bx, by) {
+                    bx += 1;
+                }
+                runs.push((start, bx - start));
+            }
+            bx += 1;
+        }
+        let mut next = Vec::with_capacity(runs.len());
+        for (x, w) in runs {
+            match open.iter().position(|r| r.x == x && r.w == w) {
+                Some(i) => {
+                    let mut rect = open.swap_remove(i);
+                    rect.h += 1;
+                    next.push(rect);
+                }
+                None => next.push(Rect { x, y: by, w, h: 1 }),
+            }
+        }
+        rects.append(&mut open);
+        open = next;
+    }
+    rects.append(&mut open);
+    //from blocks to pixels, clipped to the frame
+    for rect in &mut rects {
+        rect.x *= BLOCK_SIZE;
+        rect.y *= BLOCK_SIZE;
+        rect.w = (rect.w * BLOCK_SIZE).min(header.width - rect.x);
+        rect.h = (rect.h * BLOCK_SIZE).min(header.height - rect.y);
+    }
+    rects
+}
+
+pub(crate) fn encode(header: &Header, frame: &[u8], rects: &[Rect]) -> Vec<u8> {
+    let bpp = header.pixel_format.get_size();
+    let stride = header.width as usize * bpp;
+    let mut out = Vec::new();
+    out.extend_from_slice(&(rects.len() as u32).to_be_bytes());
+    for rect in rects {
+        for value in [rect.x, rect.y, rect.w, rect.h] {
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+        for y in rect.y..rect.y + rect.h {
+            let start = y as usize * stride + rect.x as usize * bpp;
+            out.extend_from_slice(&frame[start..start + rect.w as usize * bpp]);
+        }
+    }
+    out
+}
+
+//copies the rectangles in `data` over `frame`, None if they are malformed
+//or do not fit in the frame
+pub(crate) fn apply(header: &Header, data: &[u8], frame: &mut [u8]) -> Option<()> {
+    let bpp = header.pixel_format.get_size();
+    let stride = header.width as usize * bpp;
+    let read_u32 = |at: usize| -> Option<u32> {
+        Some(u32::from_be_bytes(
+            data.get(at..at + 4)?.try_into().unwrap(),
+        ))
+    };
+    let count = read_u32(0)?;
+    let mut at = 4;
+    for _ in 0..count {
+        let x = read_u32(at)?;
+        let y = read_u32(at + 4)?;
+        let w = read_u32(at + 8)?;
+        let h = read_u32(at + 12)?;
+        at += RECT_HEADER_SIZE;
+        if x.checked_add(w)? > header.width || y.checked_add(h)? > header.height {
+            return None;
+        }
+        let row_len = w as usize * bpp;
+        for row in y..y + h {
+            let start = row as usize * stride + x as usize * bpp;
+            frame[start..start + row_len].copy_from_slice(data.get(at..at + row_len)?);
+            at += row_len;
+        }
+    }
+    (at == data.len()).then_some(())
+}
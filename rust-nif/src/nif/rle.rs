@@ -0,0 +1,104 @@
+//Run-length encoding over pixel values, used when FEATURE_FLAGS_RLE is set.
+//
+//Every row is encoded on its own as a list of packets in the style of
+//PackBits. A packet starts with a count byte n: below 128 it is followed by
+//n + 1 literal pixels, otherwise by one pixel repeated n - 126 times.
+const MAX_LITERAL: usize = 128;
+const MIN_RUN: usize = 2;
+const MAX_RUN: usize = 129;
+
+pub(crate) fn encode(data: &[u8], pixel_size: usize, width: usize) -> Vec<u8> {
+    let mut out = Vec::new();
+    let stride = width * pixel_size;
+    if stride == 0 {
+        return out;
+    }
+    for row in data.chunks_exact(stride) {
+        let pixels: Vec<&[u8]> = row.chunks_exact(pixel_size).collect();
+        let mut x = 0;
+        let mut literal_start = 0;
+        while x < pixels.len() {
+            let mut run = 1;
+            while x + run < pixels.len() && run < MAX_RUN && pixels[x + run] == pixels[x] {
+                run += 1;
+            }
+            if run >= MIN_RUN {
+                write_literals(&mut out, &pixels[literal_start..x]);
+                out.push((run + 126) as u8);
+                out.extend_from_slice(pixels[x]);
+                x += run;
+                literal_start = x;
+            } else {
+                x += 1;
+            }
+        }
+        write_literals(&mut out, &pixels[literal_start..]);
+    }
+    out
+}
+
+fn write_literals(out: &mut Vec<u8>, pixels: &[&[u8]]) {
+    for chunk in pixels.chunks(MAX_LITERAL) {
+        out.push((chunk.len() - 1) as u8);
+        for pixel in chunk {
+            out.extend_from_slice(pixel);
+        }
+    }
+}
+
+//decodes rows from the start of `data` until `rows` rows are done or the
+//next row is incomplete or malformed, returns them and the bytes they used
+pub(crate) fn decode_rows(
+    data: &[u8],
+    pixel_size: usize,
+    width: usize,
+    rows: usize,
+) -> (Vec<u8>, usize) {
+    let stride = width * pixel_size;
+    //the rows come from a header that may be corrupt, but a byte of data
+    //never makes more than MAX_RUN pixels
+    let capacity = stride.saturating_mul(rows);
+    let mut out = Vec::with_capacity(capacity.min(data.len().saturating_mul(MAX_RUN * pixel_size)));
+    let mut at = 0;
+    for _ in 0..rows {
+        match decode_row(&data[at..], pixel_size, stride, &mut out) {
+            Some(used) => at += used,
+            None => break,
+        }
+    }
+    (out, at)
+}
+
+//appends one row of `stride` bytes to `out`, returns the bytes it used
+fn decode_row(data: &[u8], pixel_size: usize, stride: usize, out: &mut Vec<u8>) -> Option<usize> {
+    let row_start = out.len();
+    let mut at = 0;
+    let result = loop {
+        if out.len() == row_start + stride {
+            break Some(at);
+        }
+        let Some(&n) = data.get(at) else {
+            break None;
+        };
+        let n = n as usize;
+        at += 1;
+        let (len, count) = match n < MAX_LITERAL {
+            true => ((n + 1) * pixel_size, 1),
+            false => (pixel_size, n - 126),
+        };
+        let Some(pixels) = data.get(at..at + len) else {
+            break None;
+        };
+        if out.len() + len * count > row_start + stride {
+            break None;
+        }
+        for _ in 0..count {
+            out.extend_from_slice(pixels);
+        }
+        at += len;
+    };
+    if result.is_none() {
+        out.truncate(row_start);
+    }
+    result
+}
@@ -0,0 +1,91 @@
+//Tiled frames, the frame layout used with FEATURE_FLAGS_TILES.
+//
+//A frame is cut into tiles of TILE_SIZE by TILE_SIZE pixels, clipped at the
+//right and bottom edges, and every tile is stored on its own like a whole
+//frame: filtered or run-length encoded and compressed. A tiled frame record
+//starts with a table holding the stored size of every tile (u32), row by row,
+//followed by the tiles in the same order.
+use super::{Header, Result};
+
+pub const TILE_SIZE: u32 = 256;
+pub(crate) const TABLE_ENTRY_SIZE: usize = 4;
+
+/// One tile of a tiled frame.
+///
+/// `data` holds the `width * height` pixels of the tile row by row, in the
+/// pixel format of the stream. Tiles on the right and bottom edges of a
+/// frame are smaller than `TILE_SIZE`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Tile {
+    //position of the top left pixel in the frame
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+//number of tiles across and down
+pub(crate) fn tile_counts(header: &Header) -> (u32, u32) {
+    (
+        header.width.div_ceil(TILE_SIZE),
+        header.height.div_ceil(TILE_SIZE),
+    )
+}
+
+//a header with the dimensions of tile (`tx`, `ty`), for encoding the tile
+//like a frame
+pub(crate) fn tile_header(header: &Header, tx: u32, ty: u32) -> Header {
+    Header {
+        width: TILE_SIZE.min(header.width - tx * TILE_SIZE),
+        height: TILE_SIZE.min(header.height - ty * TILE_SIZE),
+        ..*header
+    }
+}
+
+//calls `f` for every tile, row by row, and collects the results. With the
+//`parallel` feature tiles are handled on the rayon thread pool
+pub(crate) fn map_tiles<T: Send>(
+    header: &Header,
+    f: impl Fn(u32, u32) -> Result<T> + Sync,
+) -> Result<Vec<T>> {
+    let (tiles_x, tiles_y) = tile_counts(header);
+    let tiles = (0..tiles_y).flat_map(|ty| (0..tiles_x).map(move |tx| (tx, ty)));
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        let tiles: Vec<_> = tiles.collect();
+        tiles.into_par_iter().map(|(tx, ty)| f(tx, ty)).collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    tiles.map(|(tx, ty)| f(tx, ty)).collect()
+}
+
+//copies the pixels of tile (`tx`, `ty`) out of a whole frame
+pub(crate) fn extract(header: &Header, frame: &[u8], tx: u32, ty: u32) -> Vec<u8> {
+    let bpp = header.pixel_format.get_size();
+    let stride = header.width as usize * bpp;
+    let tile = tile_header(header, tx, ty);
+    let row_len = tile.width as usize * bpp;
+    let mut out = Vec::with_capacity(row_len * tile.height as usize);
+    for y in ty * TILE_SIZE..ty * TILE_SIZE + tile.height {
+        let start = y as usize * stride + (tx * TILE_SIZE) as usize * bpp;
+        out.extend_from_slice(&frame[start..start + row_len]);
+    }
+    out
+}
+
+//copies the pixels of tile (`tx`, `ty`) into a whole frame
+pub(crate) fn insert(header: &Header, frame: &mut [u8], tx: u32, ty: u32, data: &[u8]) {
+    let bpp = header.pixel_format.get_size();
+    let stride = header.width as usize * bpp;
+    let tile = tile_header(header, tx, ty);
+    let row_len = tile.width as usize * bpp;
+    for (row, y) in data
+        .chunks_exact(row_len)
+        .zip(ty * TILE_SIZE..ty * TILE_SIZE + tile.height)
+    {
+        let start = y as usize * stride + (tx * TILE_SIZE) as usize * bpp;
+        frame[start..start + row_len].copy_from_slice(row);
+    }
+}
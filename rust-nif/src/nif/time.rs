@@ -0,0 +1,216 @@
+//Per-frame timestamps, stored in the `time` chunk of version 0.2 and up.
+//The chunk comes before the DATA chunk, or after it when the frames were
+//written one at a time.
+//
+//The chunk holds the timebase, the length of a tick in seconds as a
+//numerator (u32) and denominator (u32), followed by the presentation time of
+//every frame in ticks (u64). Timestamps never decrease, and a frame is shown
+//from its timestamp until the timestamp of the next frame.
+use super::{Error, Frame, Result};
+
+/// A fraction of two integers, such as the length of a tick in seconds.
+///
+/// Ordered by numerator first, not by value.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Rational {
+    pub num: u32,
+    pub den: u32,
+}
+
+impl Rational {
+    pub const fn new(num: u32, den: u32) -> Self {
+        Rational { num, den }
+    }
+    pub fn to_f64(self) -> f64 {
+        self.num as f64 / self.den as f64
+    }
+    //The simplest fraction that converts back to `value`, as far as u32 can
+    //hold it. 0/1 for NaN and values below 0, u32::MAX/1 for values too
+    //large to fit.
+    pub fn from_f32(value: f32) -> Rational {
+        if value.is_nan() || value <= 0.0 {
+            return Rational::new(0, 1);
+        }
+        if value >= u32::MAX as f32 {
+            return Rational::new(u32::MAX, 1);
+        }
+        //the convergents of the continued fraction of `value`, the last two
+        //are num0/den0 and num1/den1
+        let (mut num0, mut num1) = (0_u64, 1_u64);
+        let (mut den0, mut den1) = (1_u64, 0_u64);
+        let mut rest = value as f64;
+        loop {
+            let a = rest.floor();
+            let next = |prev: u64, prev2: u64| {
+                (a as u64)
+                    .checked_mul(prev)?
+                    .checked_add(prev2)
+                    .filter(|&n| n <= u32::MAX as u64)
+            };
+            let (Some(num), Some(den)) = (next(num1, num0), next(den1, den0)) else {
+                break;
+            };
+            (num0, num1, den0, den1) = (num1, num, den1, den);
+            if (num as f64 / den as f64) as f32 == value || rest == a {
+                break;
+            }
+            rest = 1.0 / (rest - a);
+        }
+        Rational::new(num1 as u32, den1 as u32)
+    }
+}
+
+/// When a frame is shown, in ticks of a timebase.
+///
+/// Ordered by ticks first, so only timestamps in the same timebase compare
+/// by time.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Timestamp {
+    pub ticks: u64,
+    //the length of a tick in seconds, 1/1000 for milliseconds
+    pub timebase: Rational,
+}
+
+impl Timestamp {
+    pub fn new(ticks: u64, timebase: Rational) -> Self {
+        Timestamp { ticks, timebase }
+    }
+    pub fn seconds(&self) -> f64 {
+        self.ticks as f64 * self.timebase.to_f64()
+    }
+}
+
+//the contents of the chunk
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) struct Timestamps {
+    pub(crate) timebase: Rational,
+    pub(crate) ticks: Vec<u64>,
+}
+
+impl Timestamps {
+    pub(crate) fn get(&self, index: u32) -> Option<Timestamp> {
+        let ticks = *self.ticks.get(index as usize)?;
+        Some(Timestamp::new(ticks, self.timebase))
+    }
+    //the frame shown at `seconds`, the first frame for earlier times
+    pub(crate) fn frame_at(&self, seconds: f64) -> Option<u32> {
+        if self.ticks.is_empty() {
+            return None;
+        }
+        let shown = self
+            .ticks
+            .partition_point(|&ticks| Timestamp::new(ticks, self.timebase).seconds() <= seconds);
+        Some(shown.saturating_sub(1) as u32)
+    }
+}
+
+//what adding frames one at a time has to know about the frames before
+#[derive(Clone, Copy, Default, Debug)]
+pub(crate) struct Clock {
+    //the timebase of the first frame, None if it has no timestamp
+    timebase: Option<Rational>,
+    //the ticks of the frame before
+    last: u64,
+}
+
+impl Clock {
+    pub(crate) fn timebase(&self) -> Option<Rational> {
+        self.timebase
+    }
+    //checks the timestamp of frame number `index` against the frames before
+    //it and moves on to it. `offset` is only used for errors
+    pub(crate) fn advance(
+        &mut self,
+        timestamp: Option<Timestamp>,
+        index: usize,
+        offset: u64,
+    ) -> Result<()> {
+        let invalid = |reason| Error::InvalidTimestamp {
+            offset,
+            index,
+            reason,
+        };
+        if index == 0 {
+            if let Some(t) = timestamp {
+                if t.timebase.num == 0 || t.timebase.den == 0 {
+                    return Err(invalid("the timebase is zero"));
+                }
+                self.last = t.ticks;
+            }
+            self.timebase = timestamp.map(|t| t.timebase);
+            return Ok(());
+        }
+        match (self.timebase, timestamp) {
+            (None, None) => Ok(()),
+            (None, Some(_)) => Err(invalid("the first frame has no timestamp")),
+            (Some(timebase), Some(t)) if t.timebase == timebase => {
+                if t.ticks < self.last {
+                    return Err(invalid(
+                        "the timestamp is before the one of the frame before it",
+                    ));
+                }
+                self.last = t.ticks;
+                Ok(())
+            }
+            (Some(_), _) => Err(invalid(
+                "every frame needs a timestamp in the timebase of the first one",
+            )),
+        }
+    }
+}
+
+//the timestamps of all frames, None if no frame has one. `offset` is where
+//the chunk would be written, only used for errors
+pub(crate) fn collect(frames: &[Frame], offset: u64) -> Result<Option<Timestamps>> {
+    let mut clock = Clock::default();
+    let mut ticks = Vec::new();
+    for (index, frame) in frames.iter().enumerate() {
+        clock.advance(frame.timestamp(), index, offset)?;
+        ticks.extend(frame.timestamp().map(|t| t.ticks));
+    }
+    Ok(clock
+        .timebase()
+        .map(|timebase| Timestamps { timebase, ticks }))
+}
+
+pub(crate) fn encode(timestamps: &Timestamps) -> Vec<u8> {
+    let mut out = Vec::with_capacity(8 + timestamps.ticks.len() * 8);
+    out.extend_from_slice(&timestamps.timebase.num.to_be_bytes());
+    out.extend_from_slice(&timestamps.timebase.den.to_be_bytes());
+    for ticks in &timestamps.ticks {
+        out.extend_from_slice(&ticks.to_be_bytes());
+    }
+    out
+}
+
+//`offset` is where the chunk data starts in the stream
+pub(crate) fn decode(data: &[u8], offset: u64) -> Result<Timestamps> {
+    if data.len() < 8 || !data.len().is_multiple_of(8) {
+        return Err(Error::Corrupt {
+            offset,
+            reason: "malformed timestamps",
+        });
+    }
+    let num = u32::from_be_bytes(data[0..4].try_into().unwrap());
+    let den = u32::from_be_bytes(data[4..8].try_into().unwrap());
+    if num == 0 || den == 0 {
+        return Err(Error::Corrupt {
+            offset,
+            reason: "the timebase is zero",
+        });
+    }
+    let ticks: Vec<u64> = data[8..]
+        .chunks_exact(8)
+        .map(|t| u64::from_be_bytes(t.try_into().unwrap()))
+        .collect();
+    if let Some(i) = ticks.windows(2).position(|w| w[1] < w[0]) {
+        return Err(Error::Corrupt {
+            offset: offset + 8 + (i as u64 + 1) * 8,
+            reason: "timestamps decrease",
+        });
+    }
+    Ok(Timestamps {
+        timebase: Rational::new(num, den),
+        ticks,
+    })
+}
@@ -0,0 +1,676 @@
+use std::borrow::Cow;
+use std::io::{self, Seek, SeekFrom, Write};
+
+use flate2::write::GzEncoder;
+
+use super::chunks;
+use super::codec::{self, Codec};
+use super::counting::Counting;
+use super::filter::RowFilter;
+use super::interlace;
+use super::mipmap::{self, MipmapFilter};
+use super::records::{self, IndexEntry};
+use super::rects;
+use super::rle;
+use super::tiles;
+use super::time::{self, Clock, Timestamps};
+use super::{
+    check_features, frame_size, is_chunked, write_prefix, Error, Frame, Header, Prefix, Result,
+    CURRENT_VERSION, DEFAULT_KEYFRAME_INTERVAL, FEATURE_FLAGS_DELTA, FEATURE_FLAGS_DIRTY_RECTS,
+    FEATURE_FLAGS_FILTER, FEATURE_FLAGS_FRAME_INDEX, FEATURE_FLAGS_INTERLACE,
+    FEATURE_FLAGS_MIPMAPS, FEATURE_FLAGS_RLE, FEATURE_FLAGS_TILES, FRAME_COUNT_UNKNOWN,
+};
+
+//offset of the frame count field from the start of the stream
+fn frame_count_offset(version: u32) -> u64 {
+    match is_chunked(version) {
+        true => 12 + chunks::CHUNK_HEADER_SIZE + 12,
+        false => 24,
+    }
+}
+
+//where the frames go, either as is, through a single gzip stream or as one
+//record per frame
+enum Sink<W: Write> {
+    Plain(Counting<W>),
+    Gzip(Box<GzEncoder<Counting<W>>>),
+    Records {
+        buf: Counting<W>,
+        payload_start: u64,
+        index: Vec<IndexEntry>,
+    },
+}
+
+impl<W: Write> Sink<W> {
+    //offset in the underlying stream, not in the uncompressed data
+    fn position(&self) -> u64 {
+        match self {
+            Sink::Plain(buf) => buf.position(),
+            Sink::Gzip(enc) => enc.get_ref().position(),
+            Sink::Records { buf, .. } => buf.position(),
+        }
+    }
+    //writes one whole encoded frame, `kind` is the record type for frame
+    //records
+    fn write_frame(&mut self, kind: u8, data: &[u8]) -> Result<()> {
+        let result = match self {
+            Sink::Plain(buf) => buf.write_all(data),
+            Sink::Gzip(enc) => enc.write_all(data),
+            Sink::Records {
+                buf,
+                payload_start,
+                index,
+            } => {
+                let position = buf.position();
+                records::write_record(buf, kind, data)?;
+                index.push(IndexEntry {
+                    offset: position - *payload_start,
+                    length: data.len() as u32,
+                    kind,
+                });
+                return Ok(());
+            }
+        };
+        result.map_err(|e| Error::from_io(e, self.position()))
+    }
+    //writes a record that is not part of the index, only for frame records
+    fn write_ancillary(&mut self, kind: u8, data: &[u8]) -> Result<()> {
+        match self {
+            Sink::Records { buf, .. } => records::write_record(buf, kind, data),
+            _ => unreachable!(),
+        }
+    }
+    //ends the compressed stream and flushes everything to the writer
+    fn finish(self) -> Result<Counting<W>> {
+        let position = self.position();
+        let mut buf = match self {
+            Sink::Plain(buf) => buf,
+            Sink::Gzip(enc) => enc.finish().map_err(|e| Error::from_io(e, position))?,
+            Sink::Records {
+                mut buf,
+                payload_start,
+                index,
+            } => {
+                records::write_index(&mut buf, payload_start, &index)?;
+                buf
+            }
+        };
+        let position = buf.position();
+        buf.flush().map_err(|e| Error::from_io(e, position))?;
+        Ok(buf)
+    }
+}
+
+//turns frames into the data stored for them. It holds nothing of the
+//stream, so frame records can be encoded on several threads at once
+struct Encoder {
+    header: Header,
+    frame_size: usize,
+    filter: bool,
+    //None unless frames may depend on the frame before them
+    keyframe_interval: Option<u32>,
+    delta: bool,
+    dirty_rects: bool,
+    rle: bool,
+    tiles: bool,
+    interlace: bool,
+    //the filter for the mipmap levels written after the first frame
+    mipmaps: Option<MipmapFilter>,
+    //compresses frame records, a single gzip stream is compressed by the sink
+    codec: Option<&'static dyn Codec>,
+    level: Option<i32>,
+}
+
+impl Encoder {
+    //the record type and stored data of frame number `index`, `previous` is
+    //the frame before it. `offset` is only used for errors
+    fn encode<'a>(
+        &self,
+        index: u32,
+        frame: &'a [u8],
+        previous: &[u8],
+        offset: u64,
+    ) -> Result<(u8, Cow<'a, [u8]>)> {
+        let is_keyframe = match self.keyframe_interval {
+            Some(0) => index == 0,
+            Some(interval) => index.is_multiple_of(interval),
+            None => true,
+        };
+        //rectangles are used whenever they are smaller than the frame
+        let rect_data = if self.dirty_rects && !is_keyframe {
+            let rects = rects::diff(&self.header, frame, previous);
+            let data = rects::encode(&self.header, frame, &rects);
+            (data.len() < self.frame_size).then_some(data)
+        } else {
+            None
+        };
+        let (kind, data) = match rect_data {
+            Some(data) => (records::RECORD_RECTS, Cow::Owned(data)),
+            None if self.delta && !is_keyframe => (
+                records::RECORD_DELTA,
+                Cow::Owned(records::xor(frame, previous)),
+            ),
+            None => (records::RECORD_FRAME, Cow::Borrowed(frame)),
+        };
+        let data = match kind {
+            //rectangle data has no fixed size, so its size is stored first.
+            //It is never filtered or run-length encoded
+            records::RECORD_RECTS => {
+                let len = (data.len() as u32).to_be_bytes();
+                Cow::Owned([&len[..], &self.compress(data, offset)?].concat())
+            }
+            _ if self.tiles => Cow::Owned(self.encode_tiles(&data, offset)?),
+            _ => self.pack(data, &self.header, offset)?,
+        };
+        Ok((kind, data))
+    }
+
+    //filters or run-length encodes and then compresses a whole frame, or a
+    //tile described by `header`. Interlaced frames are split into their
+    //passes first, and every pass is filtered or run-length encoded on its own
+    fn pack<'a>(&self, data: Cow<'a, [u8]>, header: &Header, offset: u64) -> Result<Cow<'a, [u8]>> {
+        let data = if self.interlace {
+            let passes = interlace::passes(header);
+            let split = interlace::split(header, &data);
+            let mut out = Vec::with_capacity(data.len());
+            for (pass, pixels) in passes.iter().zip(split) {
+                out.extend_from_slice(&self.transform(Cow::Owned(pixels), pass));
+            }
+            Cow::Owned(out)
+        } else {
+            self.transform(data, header)
+        };
+        self.compress(data, offset)
+    }
+
+    //the row filters or run-length encoding of an image described by
+    //`header`, if any
+    fn transform<'a>(&self, data: Cow<'a, [u8]>, header: &Header) -> Cow<'a, [u8]> {
+        if self.filter {
+            Cow::Owned(RowFilter::new(header).apply(&data))
+        } else if self.rle {
+            let pixel_size = header.pixel_format.get_size();
+            Cow::Owned(rle::encode(&data, pixel_size, header.width as usize))
+        } else {
+            data
+        }
+    }
+
+    fn compress<'a>(&self, data: Cow<'a, [u8]>, offset: u64) -> Result<Cow<'a, [u8]>> {
+        match self.codec {
+            Some(codec) => {
+                let pixel_size = self.header.pixel_format.get_size();
+                let compressed = codec
+                    .compress_pixels(&data, pixel_size, self.level)
+                    .map_err(|e| Error::from_io(e, offset))?;
+                Ok(Cow::Owned(compressed))
+            }
+            None => Ok(data),
+        }
+    }
+
+    //levels 1 and up of the first frame, each packed like a frame
+    fn encode_levels(
+        &self,
+        frame: &[u8],
+        filter: MipmapFilter,
+        offset: u64,
+    ) -> Result<Vec<Vec<u8>>> {
+        mipmap::levels(&self.header, frame, filter)
+            .into_iter()
+            .enumerate()
+            .map(|(n, level)| {
+                let level_header = self.header.level(n as u32 + 1);
+                Ok(self
+                    .pack(Cow::Owned(level), &level_header, offset)?
+                    .into_owned())
+            })
+            .collect()
+    }
+
+    //the size table followed by every tile packed on its own
+    fn encode_tiles(&self, frame: &[u8], offset: u64) -> Result<Vec<u8>> {
+        let packed = tiles::map_tiles(&self.header, |tx, ty| {
+            let tile = tiles::extract(&self.header, frame, tx, ty);
+            let tile_header = tiles::tile_header(&self.header, tx, ty);
+            Ok(self
+                .pack(Cow::Owned(tile), &tile_header, offset)?
+                .into_owned())
+        })?;
+        let table_len = packed.len() * tiles::TABLE_ENTRY_SIZE;
+        let mut out = Vec::with_capacity(table_len + packed.iter().map(Vec::len).sum::<usize>());
+        for tile in &packed {
+            out.extend_from_slice(&(tile.len() as u32).to_be_bytes());
+        }
+        for tile in &packed {
+            out.extend_from_slice(tile);
+        }
+        Ok(out)
+    }
+}
+
+//bytes written over what is at an offset of the stream
+type Patch = (u64, Vec<u8>);
+
+//how the frame count in the header gets its value
+enum FrameCount<W> {
+    //taken from the header passed in, checked on finish
+    Fixed,
+    //seek back to the frame count, and the length of the DATA chunk in
+    //version 0.2 streams, on finish. Offsets are relative to `start`
+    Patched {
+        start: u64,
+        patch: fn(&mut W, &[Patch]) -> io::Result<()>,
+    },
+    //left as FRAME_COUNT_UNKNOWN, readers read until the end of the stream
+    Unknown,
+}
+
+//what happens to the timestamps of pushed frames
+enum Timing {
+    //the stream already has them, or no room for them
+    Ignored,
+    //frames with a timestamp are refused for this reason
+    Rejected(&'static str),
+    //written in a `time` chunk after the DATA chunk on finish
+    Collected { clock: Clock, ticks: Vec<u64> },
+}
+
+/// How a stream is written.
+///
+/// Every function taking `impl Into<WriteOptions>` also accepts the bare
+/// feature flags, which selects the codec's default compression level.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WriteOptions {
+    pub features: u32,
+    //codec specific, None selects the codec's default
+    pub level: Option<i32>,
+    //with FEATURE_FLAGS_DELTA or FEATURE_FLAGS_DIRTY_RECTS every n-th frame
+    //is a keyframe, 0 makes the first frame the only one
+    pub keyframe_interval: u32,
+    //how the levels are computed with FEATURE_FLAGS_MIPMAPS
+    pub mipmap_filter: MipmapFilter,
+    //the container version NifWriter writes, VERSION_0_1 and up.
+    //Nif::write_to writes Nif::version instead
+    pub version: u32,
+}
+
+impl WriteOptions {
+    pub fn new(features: u32) -> Self {
+        WriteOptions {
+            features,
+            level: None,
+            keyframe_interval: DEFAULT_KEYFRAME_INTERVAL,
+            mipmap_filter: MipmapFilter::Box,
+            version: CURRENT_VERSION,
+        }
+    }
+    pub fn level(mut self, level: i32) -> Self {
+        self.level = Some(level);
+        self
+    }
+    pub fn keyframe_interval(mut self, interval: u32) -> Self {
+        self.keyframe_interval = interval;
+        self
+    }
+    pub fn mipmap_filter(mut self, filter: MipmapFilter) -> Self {
+        self.mipmap_filter = filter;
+        self
+    }
+    pub fn version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions::new(0)
+    }
+}
+
+impl From<u32> for WriteOptions {
+    fn from(features: u32) -> Self {
+        WriteOptions::new(features)
+    }
+}
+
+/// Writes a NIF stream one frame at a time.
+///
+/// Frames are written as they are pushed, so only the frame being encoded
+/// has to be held in memory. Call `finish` once all frames were pushed,
+/// dropping the writer instead leaves a stream that may be incomplete.
+///
+/// Frame timestamps are written after the frames, so only writers created
+/// with `new` for version 0.2 and up accept frames that have one.
+pub struct NifWriter<W: Write> {
+    encoder: Encoder,
+    //the last frame written, deltas and rectangles are taken against it
+    previous: Vec<u8>,
+    frames_written: u32,
+    frame_count: FrameCount<W>,
+    version: u32,
+    //offset of the DATA chunk in version 0.2 streams
+    data_chunk: Option<u64>,
+    timing: Timing,
+    sink: Sink<W>,
+}
+
+impl<W: Write + Seek> NifWriter<W> {
+    //The frame count of `header` is ignored, it is patched in by `finish`.
+    pub fn new(mut inner: W, header: Header, options: impl Into<WriteOptions>) -> Result<Self> {
+        let start = inner.stream_position().map_err(|e| Error::from_io(e, 0))?;
+        let frame_count = FrameCount::Patched {
+            start,
+            patch: patch_stream::<W>,
+        };
+        Self::create(inner, header, options.into(), frame_count)
+    }
+}
+
+impl<W: Write> NifWriter<W> {
+    //Writes a stream that must contain exactly `header.frame_count` frames.
+    pub fn with_frame_count(
+        inner: W,
+        header: Header,
+        options: impl Into<WriteOptions>,
+    ) -> Result<Self> {
+        Self::create(inner, header, options.into(), FrameCount::Fixed)
+    }
+
+    //For sinks that cannot seek: the frame count is stored as
+    //FRAME_COUNT_UNKNOWN and readers read frames until the stream ends.
+    pub fn new_streaming(
+        inner: W,
+        header: Header,
+        options: impl Into<WriteOptions>,
+    ) -> Result<Self> {
+        Self::create(inner, header, options.into(), FrameCount::Unknown)
+    }
+
+    fn create(
+        inner: W,
+        mut header: Header,
+        options: WriteOptions,
+        frame_count: FrameCount<W>,
+    ) -> Result<Self> {
+        check_features(options.features)?;
+        if !matches!(frame_count, FrameCount::Fixed) {
+            //also what readers see if we never get to patch the real count
+            header.frame_count = FRAME_COUNT_UNKNOWN;
+        }
+        let mut buf = Counting::new(inner);
+        let prefix = Prefix::new(options.version, options.features, header);
+        write_prefix(&mut buf, &prefix)?;
+        //the length is patched in by finish if the writer can seek
+        let data_chunk = is_chunked(options.version).then(|| buf.position());
+        //a chunk can only follow DATA if its length is patched in
+        let timing = match (&frame_count, data_chunk) {
+            (_, None) => Timing::Rejected("timestamps require version 0.2"),
+            (FrameCount::Patched { .. }, Some(_)) => Timing::Collected {
+                clock: Clock::default(),
+                ticks: Vec::new(),
+            },
+            _ => Timing::Rejected("timestamps require a writer that can seek"),
+        };
+        if data_chunk.is_some() {
+            chunks::write_chunk_header(&mut buf, chunks::CHUNK_DATA, chunks::LENGTH_UNKNOWN)?;
+        }
+        let mut writer = Self::with_sink(buf, header, options)?;
+        writer.frame_count = frame_count;
+        writer.version = options.version;
+        writer.data_chunk = data_chunk;
+        writer.timing = timing;
+        Ok(writer)
+    }
+
+    //`buf` must be positioned right after the header, which must contain
+    //exactly `header.frame_count` frames
+    pub(crate) fn with_sink(
+        buf: Counting<W>,
+        header: Header,
+        options: WriteOptions,
+    ) -> Result<Self> {
+        let frame_size = frame_size(&header)?;
+        let codec = codec::for_features(options.features)?;
+        let records = options.features & FEATURE_FLAGS_FRAME_INDEX != 0;
+        let sink = if records {
+            Sink::Records {
+                payload_start: buf.position(),
+                buf,
+                index: Vec::new(),
+            }
+        } else if codec.is_some() {
+            let level = codec::flate_level(options.level);
+            Sink::Gzip(Box::new(GzEncoder::new(buf, level)))
+        } else {
+            Sink::Plain(buf)
+        };
+        let encoder = Encoder {
+            header,
+            frame_size,
+            filter: options.features & FEATURE_FLAGS_FILTER != 0,
+            keyframe_interval: (options.features
+                & (FEATURE_FLAGS_DELTA | FEATURE_FLAGS_DIRTY_RECTS)
+                != 0)
+                .then_some(options.keyframe_interval),
+            delta: options.features & FEATURE_FLAGS_DELTA != 0,
+            dirty_rects: options.features & FEATURE_FLAGS_DIRTY_RECTS != 0,
+            rle: options.features & FEATURE_FLAGS_RLE != 0,
+            tiles: options.features & FEATURE_FLAGS_TILES != 0,
+            interlace: options.features & FEATURE_FLAGS_INTERLACE != 0,
+            mipmaps: (options.features & FEATURE_FLAGS_MIPMAPS != 0)
+                .then_some(options.mipmap_filter),
+            codec: codec.filter(|_| records),
+            level: options.level,
+        };
+        Ok(NifWriter {
+            encoder,
+            previous: Vec::new(),
+            frames_written: 0,
+            frame_count: FrameCount::Fixed,
+            version: options.version,
+            data_chunk: None,
+            timing: Timing::Ignored,
+            sink,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.encoder.header
+    }
+    pub fn frames_written(&self) -> u32 {
+        self.frames_written
+    }
+
+    pub fn push_frame(&mut self, frame: &Frame) -> Result<()> {
+        self.check_frame(frame, self.frames_written, &mut self.clock())?;
+        let (kind, data) = self.encoder.encode(
+            self.frames_written,
+            &frame.data,
+            &self.previous,
+            self.sink.position(),
+        )?;
+        self.write_encoded(frame, kind, &data)
+    }
+
+    //Pushes all of `frames`. With the `parallel` feature frame records are
+    //encoded on the rayon thread pool, the stream is the same as when the
+    //frames are pushed one at a time.
+    pub fn push_frames(&mut self, frames: &[Frame]) -> Result<()> {
+        #[cfg(feature = "parallel")]
+        if let Sink::Records { .. } = self.sink {
+            return self.push_parallel(frames);
+        }
+        for frame in frames {
+            self.push_frame(frame)?;
+        }
+        Ok(())
+    }
+
+    //encodes a few frames per thread at a time, so only those have to be
+    //held in memory
+    #[cfg(feature = "parallel")]
+    fn push_parallel(&mut self, frames: &[Frame]) -> Result<()> {
+        use rayon::prelude::*;
+        for batch in frames.chunks(rayon::current_num_threads() * 2) {
+            let start = self.frames_written;
+            let mut clock = self.clock();
+            let valid = (0..batch.len())
+                .take_while(|&i| {
+                    self.check_frame(&batch[i], start + i as u32, &mut clock)
+                        .is_ok()
+                })
+                .count();
+            let (encoder, previous) = (&self.encoder, &self.previous);
+            let offset = self.sink.position();
+            let encoded: Vec<_> = (0..valid)
+                .into_par_iter()
+                .map(|i| {
+                    let previous = match i {
+                        0 => previous,
+                        _ => &batch[i - 1].data,
+                    };
+                    encoder.encode(start + i as u32, &batch[i].data, previous, offset)
+                })
+                .collect();
+            for (frame, result) in batch.iter().zip(encoded) {
+                let (kind, data) = result?;
+                self.write_encoded(frame, kind, &data)?;
+            }
+            if valid < batch.len() {
+                //reports the error once the frames before it are written
+                return self.push_frame(&batch[valid]);
+            }
+        }
+        Ok(())
+    }
+
+    //a copy of the timestamps seen so far to check frames against
+    fn clock(&self) -> Clock {
+        match &self.timing {
+            Timing::Collected { clock, .. } => *clock,
+            _ => Clock::default(),
+        }
+    }
+
+    //errors unless `frame` can be written as frame number `index`, `clock`
+    //moves on to its timestamp
+    fn check_frame(&self, frame: &Frame, index: u32, clock: &mut Clock) -> Result<()> {
+        if frame.data.len() != self.encoder.frame_size {
+            return Err(Error::FrameSizeMismatch {
+                offset: self.sink.position(),
+                index: index as usize,
+                expected: self.encoder.frame_size,
+                found: frame.data.len(),
+            });
+        }
+        let limit = match self.frame_count {
+            FrameCount::Fixed => self.encoder.header.frame_count,
+            //the last value is reserved for FRAME_COUNT_UNKNOWN
+            _ => FRAME_COUNT_UNKNOWN - 1,
+        };
+        if self.encoder.mipmaps.is_some() && index > 0 {
+            return Err(Error::Unsupported {
+                offset: self.sink.position(),
+                reason: "mipmap levels are only stored for images of one frame",
+            });
+        }
+        if index >= limit {
+            return Err(Error::FrameCountMismatch {
+                offset: self.sink.position(),
+                expected: limit,
+                found: index as usize + 1,
+            });
+        }
+        match self.timing {
+            Timing::Collected { .. } => {
+                clock.advance(frame.timestamp(), index as usize, self.sink.position())
+            }
+            Timing::Rejected(reason) if frame.timestamp().is_some() => Err(Error::Unsupported {
+                offset: self.sink.position(),
+                reason,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn write_encoded(&mut self, frame: &Frame, kind: u8, data: &[u8]) -> Result<()> {
+        self.sink.write_frame(kind, data)?;
+        if let Timing::Collected { clock, ticks } = &mut self.timing {
+            //checked by check_frame already
+            let offset = self.sink.position();
+            clock.advance(frame.timestamp(), self.frames_written as usize, offset)?;
+            ticks.extend(frame.timestamp().map(|t| t.ticks));
+        }
+        if let Some(filter) = self.encoder.mipmaps {
+            let offset = self.sink.position();
+            for level in self.encoder.encode_levels(&frame.data, filter, offset)? {
+                self.sink.write_ancillary(records::RECORD_LEVEL, &level)?;
+            }
+        }
+        if self.encoder.keyframe_interval.is_some() {
+            self.previous.clone_from(&frame.data);
+        }
+        self.frames_written += 1;
+        Ok(())
+    }
+
+    //Completes the stream and returns the underlying writer.
+    pub fn finish(self) -> Result<W> {
+        let mut buf = self.sink.finish()?;
+        match self.frame_count {
+            FrameCount::Fixed if self.frames_written != self.encoder.header.frame_count => {
+                Err(Error::FrameCountMismatch {
+                    offset: buf.position(),
+                    expected: self.encoder.header.frame_count,
+                    found: self.frames_written as usize,
+                })
+            }
+            FrameCount::Patched { start, patch } => {
+                let end = buf.position();
+                if let Timing::Collected { clock, ticks } = self.timing {
+                    if let Some(timebase) = clock.timebase() {
+                        let timestamps = Timestamps { timebase, ticks };
+                        chunks::write_chunk(
+                            &mut buf,
+                            chunks::CHUNK_TIME,
+                            &time::encode(&timestamps),
+                        )?;
+                    }
+                }
+                let mut inner = buf.into_inner();
+                let offset = frame_count_offset(self.version);
+                let mut patches = vec![(offset, self.frames_written.to_be_bytes().to_vec())];
+                if let Some(chunk) = self.data_chunk {
+                    let length = end - chunk - chunks::CHUNK_HEADER_SIZE;
+                    patches.push((chunk + 4, length.to_be_bytes().to_vec()));
+                }
+                let patches: Vec<_> = patches
+                    .into_iter()
+                    .map(|(at, bytes)| (start + at, bytes))
+                    .collect();
+                patch(&mut inner, &patches).map_err(|e| Error::from_io(e, offset))?;
+                inner.flush().map_err(|e| Error::from_io(e, offset))?;
+                Ok(inner)
+            }
+            _ => {
+                let position = buf.position();
+                buf.flush().map_err(|e| Error::from_io(e, position))?;
+                Ok(buf.into_inner())
+            }
+        }
+    }
+}
+
+//writes the real frame count and DATA chunk length and returns to the end
+//of the stream
+fn patch_stream<W: Write + Seek>(inner: &mut W, patches: &[Patch]) -> io::Result<()> {
+    let end = inner.stream_position()?;
+    for (at, bytes) in patches {
+        inner.seek(SeekFrom::Start(*at))?;
+        inner.write_all(bytes)?;
+    }
+    inner.seek(SeekFrom::Start(end))?;
+    Ok(())
+}